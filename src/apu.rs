@@ -0,0 +1,822 @@
+use std::collections::VecDeque;
+
+/// The rate (in Hz) at which the APU produces stereo samples.
+///
+/// The channels are stepped every M-cycle and the mixed output is averaged
+/// over [`M_CYCLES_PER_SAMPLE`] M-cycles, which acts as a cheap low-pass filter
+/// before the signal is handed to callers.
+pub const SAMPLE_RATE: u32 = 65_536;
+
+const M_CYCLES_PER_SAMPLE: u32 = 16;
+
+// Samples are buffered until the caller drains them. If nobody is listening we
+// don't want to grow forever, so only the most recent second is kept around.
+const MAX_BUFFERED_SAMPLES: usize = SAMPLE_RATE as usize;
+
+// The real hardware has a capacitor on each output which removes the DC offset
+// of the DACs. This is the charge factor of that capacitor per output sample
+// (0.999958 per T-cycle, raised to the number of T-cycles per sample).
+const HIGH_PASS_CHARGE_FACTOR: f32 = 0.997_315;
+
+const NR10: u16 = 0xFF10;
+const NR11: u16 = 0xFF11;
+const NR12: u16 = 0xFF12;
+const NR13: u16 = 0xFF13;
+const NR14: u16 = 0xFF14;
+const NR21: u16 = 0xFF16;
+const NR22: u16 = 0xFF17;
+const NR23: u16 = 0xFF18;
+const NR24: u16 = 0xFF19;
+const NR30: u16 = 0xFF1A;
+const NR31: u16 = 0xFF1B;
+const NR32: u16 = 0xFF1C;
+const NR33: u16 = 0xFF1D;
+const NR34: u16 = 0xFF1E;
+const NR41: u16 = 0xFF20;
+const NR42: u16 = 0xFF21;
+const NR43: u16 = 0xFF22;
+const NR44: u16 = 0xFF23;
+const NR50: u16 = 0xFF24;
+const NR51: u16 = 0xFF25;
+const NR52: u16 = 0xFF26;
+
+const REGISTERS_START: u16 = NR10;
+const WAVE_RAM_START: u16 = 0xFF30;
+const WAVE_RAM_END: u16 = 0xFF3F;
+
+// Bits that always read back as 1, either because they are unused or because
+// they are write-only. Indexed by `address - REGISTERS_START`.
+#[rustfmt::skip]
+const READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // (unused), NR21-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // (unused), NR41-NR44
+    0x00, 0x00, 0x70, 0xFF, 0xFF, // NR50-NR52, (unused)
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, // (unused)
+];
+
+#[rustfmt::skip]
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1], // 12.5%
+    [1, 0, 0, 0, 0, 0, 0, 1], // 25%
+    [1, 0, 0, 0, 0, 1, 1, 1], // 50%
+    [0, 1, 1, 1, 1, 1, 1, 0], // 75%
+];
+
+const NOISE_DIVISORS: [i32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StereoSample {
+    pub left: f32,
+    pub right: f32,
+}
+
+/// The Audio Processing Unit, mapped to 0xFF10-0xFF3F.
+pub struct Apu {
+    powered: bool,
+    registers: [u8; 0x20],
+
+    square1: SquareChannel,
+    square2: SquareChannel,
+    wave: WaveChannel,
+    noise: NoiseChannel,
+
+    frame_sequencer_step: u8,
+
+    accumulator: StereoSample,
+    accumulated_cycles: u32,
+    capacitor: StereoSample,
+    samples: VecDeque<StereoSample>,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Self {
+            powered: false,
+            registers: [0; 0x20],
+            square1: SquareChannel::new(true),
+            square2: SquareChannel::new(false),
+            wave: WaveChannel::new(),
+            noise: NoiseChannel::new(),
+            frame_sequencer_step: 0,
+            accumulator: StereoSample::default(),
+            accumulated_cycles: 0,
+            capacitor: StereoSample::default(),
+            samples: VecDeque::with_capacity(MAX_BUFFERED_SAMPLES),
+        }
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            NR52 => {
+                let mut value = READ_MASKS[(NR52 - REGISTERS_START) as usize];
+                value |= (self.powered as u8) << 7;
+                value |= self.square1.enabled as u8;
+                value |= (self.square2.enabled as u8) << 1;
+                value |= (self.wave.enabled as u8) << 2;
+                value |= (self.noise.enabled as u8) << 3;
+                value
+            },
+            REGISTERS_START..=0xFF2F => {
+                let idx = (address - REGISTERS_START) as usize;
+                self.registers[idx] | READ_MASKS[idx]
+            },
+            WAVE_RAM_START..=WAVE_RAM_END => self.wave.ram[(address - WAVE_RAM_START) as usize],
+            _ => unreachable!("address {:#06X} is not mapped to the APU", address),
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            NR52 => {
+                let powered = value & 0x80 != 0;
+                if self.powered && !powered {
+                    self.power_off();
+                } else if !self.powered && powered {
+                    self.frame_sequencer_step = 0;
+                }
+                self.powered = powered;
+            },
+            // On the DMG, the length counters can still be loaded while the APU
+            // is powered off. Every other register ignores writes.
+            _ if !self.powered && (REGISTERS_START..=0xFF2F).contains(&address) => match address {
+                NR11 => self.square1.length.load(value & 0x3F),
+                NR21 => self.square2.length.load(value & 0x3F),
+                NR31 => self.wave.length.load(value),
+                NR41 => self.noise.length.load(value & 0x3F),
+                _ => {},
+            },
+            REGISTERS_START..=0xFF2F => {
+                self.registers[(address - REGISTERS_START) as usize] = value;
+                match address {
+                    NR10 => self.square1.write_sweep(value),
+                    NR11 => self.square1.write_duty_length(value),
+                    NR12 => self.square1.write_envelope(value),
+                    NR13 => self.square1.write_frequency_low(value),
+                    NR14 => self.square1.write_control(value),
+                    NR21 => self.square2.write_duty_length(value),
+                    NR22 => self.square2.write_envelope(value),
+                    NR23 => self.square2.write_frequency_low(value),
+                    NR24 => self.square2.write_control(value),
+                    NR30 => self.wave.write_dac(value),
+                    NR31 => self.wave.length.load(value),
+                    NR32 => self.wave.output_level = (value >> 5) & 0x03,
+                    NR33 => self.wave.write_frequency_low(value),
+                    NR34 => self.wave.write_control(value),
+                    NR41 => self.noise.length.load(value & 0x3F),
+                    NR42 => self.noise.write_envelope(value),
+                    NR43 => self.noise.write_polynomial(value),
+                    NR44 => self.noise.write_control(value),
+                    // NR50 and NR51 are only ever read back out of `registers`.
+                    _ => {},
+                }
+            },
+            WAVE_RAM_START..=WAVE_RAM_END => {
+                self.wave.ram[(address - WAVE_RAM_START) as usize] = value;
+            },
+            _ => unreachable!("address {:#06X} is not mapped to the APU", address),
+        }
+    }
+
+    /// Advances the APU by a single M-cycle.
+    pub fn tick(&mut self) {
+        if self.powered {
+            self.square1.tick(4);
+            self.square2.tick(4);
+            self.wave.tick(4);
+            self.noise.tick(4);
+        }
+
+        let mixed = self.mix();
+        self.accumulator.left += mixed.left;
+        self.accumulator.right += mixed.right;
+        self.accumulated_cycles += 1;
+
+        if self.accumulated_cycles == M_CYCLES_PER_SAMPLE {
+            let sample = StereoSample {
+                left: self.accumulator.left / M_CYCLES_PER_SAMPLE as f32,
+                right: self.accumulator.right / M_CYCLES_PER_SAMPLE as f32,
+            };
+            let sample = self.high_pass(sample);
+            if self.samples.len() == MAX_BUFFERED_SAMPLES {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(sample);
+            self.accumulator = StereoSample::default();
+            self.accumulated_cycles = 0;
+        }
+    }
+
+    /// Clocks the 512 Hz frame sequencer, which drives the length counters,
+    /// the frequency sweep and the volume envelopes.
+    ///
+    /// This is called by the system every time bit 4 of DIV goes from 1 to 0.
+    // `is_multiple_of` needs a newer toolchain than anything else here does.
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn clock_frame_sequencer(&mut self) {
+        if !self.powered {
+            return;
+        }
+
+        if self.frame_sequencer_step % 2 == 0 {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.frame_sequencer_step == 2 || self.frame_sequencer_step == 6 {
+            self.square1.clock_sweep();
+        }
+        if self.frame_sequencer_step == 7 {
+            self.square1.envelope.clock();
+            self.square2.envelope.clock();
+            self.noise.envelope.clock();
+        }
+
+        self.frame_sequencer_step = (self.frame_sequencer_step + 1) % 8;
+    }
+
+    /// Removes and returns all the samples produced since the last call.
+    pub fn drain_samples(&mut self) -> impl Iterator<Item = StereoSample> + '_ {
+        self.samples.drain(..)
+    }
+
+    fn power_off(&mut self) {
+        self.registers = [0; 0x20];
+        self.square1 = SquareChannel { length: self.square1.length, ..SquareChannel::new(true) };
+        self.square2 = SquareChannel { length: self.square2.length, ..SquareChannel::new(false) };
+        self.wave =
+            WaveChannel { length: self.wave.length, ram: self.wave.ram, ..WaveChannel::new() };
+        self.noise = NoiseChannel { length: self.noise.length, ..NoiseChannel::new() };
+    }
+
+    fn mix(&self) -> StereoSample {
+        if !self.powered {
+            return StereoSample::default();
+        }
+
+        let outputs = [
+            dac(self.square1.dac_enabled, self.square1.output()),
+            dac(self.square2.dac_enabled, self.square2.output()),
+            dac(self.wave.dac_enabled, self.wave.output()),
+            dac(self.noise.dac_enabled, self.noise.output()),
+        ];
+
+        let nr50 = self.registers[(NR50 - REGISTERS_START) as usize];
+        let nr51 = self.registers[(NR51 - REGISTERS_START) as usize];
+
+        let mut sample = StereoSample::default();
+        for (idx, output) in outputs.iter().enumerate() {
+            if nr51 & (0x10 << idx) != 0 {
+                sample.left += *output;
+            }
+            if nr51 & (0x01 << idx) != 0 {
+                sample.right += *output;
+            }
+        }
+
+        // Each side has a 3-bit master volume, where 0 is 1/8 volume rather than
+        // muted. Dividing by 4 brings the sum of the channels back into -1..1.
+        sample.left *= (((nr50 >> 4) & 0x07) + 1) as f32 / 8.0 / 4.0;
+        sample.right *= ((nr50 & 0x07) + 1) as f32 / 8.0 / 4.0;
+        sample
+    }
+
+    fn high_pass(&mut self, input: StereoSample) -> StereoSample {
+        let output = StereoSample {
+            left: input.left - self.capacitor.left,
+            right: input.right - self.capacitor.right,
+        };
+        self.capacitor.left = input.left - output.left * HIGH_PASS_CHARGE_FACTOR;
+        self.capacitor.right = input.right - output.right * HIGH_PASS_CHARGE_FACTOR;
+        output
+    }
+}
+
+// Converts a channel's 4-bit digital output into an analog value in -1..1.
+fn dac(enabled: bool, digital: u8) -> f32 {
+    if enabled {
+        digital as f32 / 7.5 - 1.0
+    } else {
+        0.0
+    }
+}
+
+#[derive(Clone, Copy)]
+struct LengthCounter {
+    enabled: bool,
+    counter: u16,
+    max: u16,
+}
+
+impl LengthCounter {
+    fn new(max: u16) -> Self {
+        Self { enabled: false, counter: 0, max }
+    }
+
+    fn load(&mut self, value: u8) {
+        self.counter = self.max - value as u16;
+    }
+
+    fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    // Returns true if the counter just expired, meaning the channel should be
+    // disabled.
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            self.counter == 0
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    period: u8,
+    timer: u8,
+    volume: u8,
+}
+
+impl Envelope {
+    fn write(&mut self, value: u8) {
+        self.initial_volume = value >> 4;
+        self.increase = value & 0x08 != 0;
+        self.period = value & 0x07;
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 0x0F {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+    timer: u8,
+    shadow_frequency: u16,
+    enabled: bool,
+}
+
+impl Sweep {
+    fn calculate(&self) -> u16 {
+        let delta = self.shadow_frequency >> self.shift;
+        if self.negate {
+            self.shadow_frequency - delta
+        } else {
+            self.shadow_frequency + delta
+        }
+    }
+
+    fn reload_timer(&mut self) {
+        // A period of 0 is treated as 8 by the timer.
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+}
+
+struct SquareChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    duty: u8,
+    duty_position: u8,
+    frequency: u16,
+    timer: i32,
+    length: LengthCounter,
+    envelope: Envelope,
+    // Only channel 1 has a frequency sweep unit.
+    sweep: Option<Sweep>,
+}
+
+impl SquareChannel {
+    fn new(with_sweep: bool) -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            duty: 0,
+            duty_position: 0,
+            frequency: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+            sweep: with_sweep.then(Sweep::default),
+        }
+    }
+
+    fn period(&self) -> i32 {
+        (2048 - self.frequency as i32) * 4
+    }
+
+    fn write_sweep(&mut self, value: u8) {
+        if let Some(sweep) = &mut self.sweep {
+            sweep.period = (value >> 4) & 0x07;
+            sweep.negate = value & 0x08 != 0;
+            sweep.shift = value & 0x07;
+        }
+    }
+
+    fn write_duty_length(&mut self, value: u8) {
+        self.duty = value >> 6;
+        self.length.load(value & 0x3F);
+    }
+
+    fn write_envelope(&mut self, value: u8) {
+        self.envelope.write(value);
+        self.dac_enabled = value & 0xF8 != 0;
+        if !self.dac_enabled {
+            self.enabled = false;
+        }
+    }
+
+    fn write_frequency_low(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0x0700) | value as u16;
+    }
+
+    fn write_control(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0x00FF) | ((value as u16 & 0x07) << 8);
+        self.length.enabled = value & 0x40 != 0;
+        if value & 0x80 != 0 {
+            self.trigger();
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger();
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        if let Some(sweep) = &mut self.sweep {
+            sweep.shadow_frequency = self.frequency;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+            if sweep.shift != 0 && sweep.calculate() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn tick(&mut self, t_cycles: i32) {
+        self.timer -= t_cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            self.duty_position = (self.duty_position + 1) % 8;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        let Some(sweep) = &mut self.sweep else {
+            return;
+        };
+
+        if sweep.timer > 0 {
+            sweep.timer -= 1;
+        }
+        if sweep.timer != 0 {
+            return;
+        }
+
+        sweep.reload_timer();
+        if !sweep.enabled || sweep.period == 0 {
+            return;
+        }
+
+        let frequency = sweep.calculate();
+        if frequency > 2047 {
+            self.enabled = false;
+        } else if sweep.shift != 0 {
+            sweep.shadow_frequency = frequency;
+            self.frequency = frequency;
+
+            // The new frequency is immediately run through the overflow check
+            // a second time, but this result is not written back.
+            if sweep.calculate() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        DUTY_TABLE[self.duty as usize][self.duty_position as usize] * self.envelope.volume
+    }
+}
+
+struct WaveChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    output_level: u8,
+    frequency: u16,
+    timer: i32,
+    position: u8,
+    sample_buffer: u8,
+    length: LengthCounter,
+    ram: [u8; 16],
+}
+
+impl WaveChannel {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            output_level: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample_buffer: 0,
+            length: LengthCounter::new(256),
+            ram: [0; 16],
+        }
+    }
+
+    fn period(&self) -> i32 {
+        (2048 - self.frequency as i32) * 2
+    }
+
+    fn write_dac(&mut self, value: u8) {
+        self.dac_enabled = value & 0x80 != 0;
+        if !self.dac_enabled {
+            self.enabled = false;
+        }
+    }
+
+    fn write_frequency_low(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0x0700) | value as u16;
+    }
+
+    fn write_control(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0x00FF) | ((value as u16 & 0x07) << 8);
+        self.length.enabled = value & 0x40 != 0;
+        if value & 0x80 != 0 {
+            self.enabled = self.dac_enabled;
+            self.length.trigger();
+            self.timer = self.period();
+            self.position = 0;
+        }
+    }
+
+    #[allow(clippy::manual_is_multiple_of)]
+    fn tick(&mut self, t_cycles: i32) {
+        self.timer -= t_cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            self.position = (self.position + 1) % 32;
+
+            // Each byte of wave RAM holds two 4-bit samples, upper nibble first.
+            let byte = self.ram[self.position as usize / 2];
+            self.sample_buffer = if self.position % 2 == 0 { byte >> 4 } else { byte & 0x0F };
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        match self.output_level {
+            0 => 0,
+            level => self.sample_buffer >> (level - 1),
+        }
+    }
+}
+
+struct NoiseChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    clock_shift: u8,
+    width_mode: bool,
+    divisor_code: u8,
+    timer: i32,
+    lfsr: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl NoiseChannel {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            clock_shift: 0,
+            width_mode: false,
+            divisor_code: 0,
+            timer: 0,
+            lfsr: 0x7FFF,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+        }
+    }
+
+    fn period(&self) -> i32 {
+        NOISE_DIVISORS[self.divisor_code as usize] << self.clock_shift
+    }
+
+    fn write_envelope(&mut self, value: u8) {
+        self.envelope.write(value);
+        self.dac_enabled = value & 0xF8 != 0;
+        if !self.dac_enabled {
+            self.enabled = false;
+        }
+    }
+
+    fn write_polynomial(&mut self, value: u8) {
+        self.clock_shift = value >> 4;
+        self.width_mode = value & 0x08 != 0;
+        self.divisor_code = value & 0x07;
+    }
+
+    fn write_control(&mut self, value: u8) {
+        self.length.enabled = value & 0x40 != 0;
+        if value & 0x80 != 0 {
+            self.enabled = self.dac_enabled;
+            self.length.trigger();
+            self.timer = self.period();
+            self.lfsr = 0x7FFF;
+            self.envelope.trigger();
+        }
+    }
+
+    fn tick(&mut self, t_cycles: i32) {
+        self.timer -= t_cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+
+            let xor = (self.lfsr & 0x01) ^ ((self.lfsr >> 1) & 0x01);
+            self.lfsr = (self.lfsr >> 1) | (xor << 14);
+            if self.width_mode {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (xor << 6);
+            }
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        // The output is the inverse of bit 0 of the LFSR.
+        (!self.lfsr & 0x01) as u8 * self.envelope.volume
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn powered_apu() -> Apu {
+        let mut apu = Apu::new();
+        apu.write(NR52, 0x80);
+        apu
+    }
+
+    #[test]
+    fn unused_and_write_only_bits_read_as_one() {
+        let mut apu = powered_apu();
+        apu.write(NR11, 0x00);
+        apu.write(NR13, 0x00);
+        apu.write(NR30, 0x00);
+        assert_eq!(apu.read(NR10), 0x80);
+        assert_eq!(apu.read(NR11), 0x3F);
+        assert_eq!(apu.read(NR13), 0xFF);
+        assert_eq!(apu.read(NR30), 0x7F);
+        assert_eq!(apu.read(0xFF15), 0xFF);
+        assert_eq!(apu.read(NR52), 0xF0);
+    }
+
+    #[test]
+    fn powering_off_clears_registers_and_ignores_writes() {
+        let mut apu = powered_apu();
+        apu.write(NR50, 0x77);
+        apu.write(WAVE_RAM_START, 0xAB);
+
+        apu.write(NR52, 0x00);
+        assert_eq!(apu.read(NR50), 0x00);
+        assert_eq!(apu.read(NR52), 0x70);
+
+        apu.write(NR50, 0x77);
+        assert_eq!(apu.read(NR50), 0x00);
+
+        // Wave RAM is not affected by power.
+        assert_eq!(apu.read(WAVE_RAM_START), 0xAB);
+    }
+
+    #[test]
+    fn trigger_enables_channel_and_length_counter_disables_it() {
+        let mut apu = powered_apu();
+        apu.write(NR22, 0xF0);
+        apu.write(NR21, 0x3E); // Length of 64 - 62 = 2.
+        apu.write(NR24, 0xC0); // Trigger with length enabled.
+        assert_eq!(apu.read(NR52) & 0x02, 0x02);
+
+        // Step 0 clocks the length counters, step 1 does not.
+        apu.clock_frame_sequencer();
+        apu.clock_frame_sequencer();
+        assert_eq!(apu.read(NR52) & 0x02, 0x02);
+
+        apu.clock_frame_sequencer();
+        assert_eq!(apu.read(NR52) & 0x02, 0x00);
+    }
+
+    #[test]
+    fn trigger_with_dac_off_does_not_enable_channel() {
+        let mut apu = powered_apu();
+        apu.write(NR42, 0x00);
+        apu.write(NR44, 0x80);
+        assert_eq!(apu.read(NR52) & 0x08, 0x00);
+    }
+
+    #[test]
+    fn sweep_overflow_disables_channel() {
+        let mut apu = powered_apu();
+        apu.write(NR10, 0x11); // Period 1, increase, shift 1.
+        apu.write(NR12, 0xF0);
+        apu.write(NR13, 0x00);
+        apu.write(NR14, 0x85); // Trigger with frequency 0x500.
+        assert_eq!(apu.read(NR52) & 0x01, 0x01);
+
+        // 0x500 + (0x500 >> 1) = 0x780, and the follow-up check of
+        // 0x780 + (0x780 >> 1) overflows.
+        for _ in 0..4 {
+            apu.clock_frame_sequencer();
+        }
+        assert_eq!(apu.read(NR52) & 0x01, 0x00);
+    }
+
+    #[test]
+    fn samples_are_produced_at_the_sample_rate() {
+        let mut apu = powered_apu();
+        // One second of emulated time is 2^20 M-cycles.
+        for _ in 0..1 << 20 {
+            apu.tick();
+        }
+        assert_eq!(apu.drain_samples().count(), SAMPLE_RATE as usize);
+        assert_eq!(apu.drain_samples().count(), 0);
+    }
+
+    #[test]
+    fn panning_routes_channels_to_each_side() {
+        let mut apu = powered_apu();
+        apu.write(NR50, 0x77);
+        apu.write(NR51, 0x10); // Channel 1 on the left only.
+        apu.write(NR11, 0x80);
+        apu.write(NR12, 0xF0);
+        apu.write(NR14, 0x87);
+
+        let mut left = 0.0f32;
+        let mut right = 0.0f32;
+        for _ in 0..M_CYCLES_PER_SAMPLE * 64 {
+            apu.tick();
+        }
+        for sample in apu.drain_samples() {
+            left += sample.left.abs();
+            right += sample.right.abs();
+        }
+        assert!(left > 0.0);
+        assert_eq!(right, 0.0);
+    }
+}
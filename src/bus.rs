@@ -0,0 +1,160 @@
+use crate::error::EmulationError;
+
+/// What the CPU is connected to: the memory map, and everything that is clocked
+/// along with it.
+///
+/// A [`crate::Gameboy`] normally runs on its own hardware ([`crate::System`]),
+/// but can run on any bus, e.g. a [`TestBus`] to test the CPU on its own.
+pub trait Bus {
+    /// Reads a byte for the CPU.
+    fn read(&mut self, address: u16) -> Result<u8, EmulationError>;
+
+    /// Writes a byte for the CPU.
+    fn write(&mut self, address: u16, value: u8);
+
+    /// Reads a byte without any side effects, e.g. to show it in the debugger.
+    /// Fails exactly when [`Bus::read`] would.
+    fn peek(&self, address: u16) -> Result<u8, EmulationError>;
+
+    /// Writes a byte without any side effects, e.g. to patch memory from the
+    /// debugger. Memory that can't be written this way is left as it is.
+    fn poke(&mut self, address: u16, value: u8);
+
+    /// Called at the end of every M-cycle, to advance everything that is
+    /// clocked independently of the CPU.
+    fn tick(&mut self);
+
+    /// Removes and returns the last access made since this was last called,
+    /// which is how the debugger watches memory. Buses that don't keep track of
+    /// their accesses always return None.
+    fn take_access_event(&mut self) -> Option<AccessEvent> {
+        None
+    }
+}
+
+/// A bus access made by the CPU.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BusAccess {
+    Read { address: u16, value: u8 },
+    Write { address: u16, value: u8 },
+}
+
+impl BusAccess {
+    pub fn address(self) -> u16 {
+        match self {
+            Self::Read { address, .. } | Self::Write { address, .. } => address,
+        }
+    }
+
+    /// The value read or written.
+    pub fn value(self) -> u8 {
+        match self {
+            Self::Read { value, .. } | Self::Write { value, .. } => value,
+        }
+    }
+}
+
+/// A bus access along with the value that was at its address before it, which
+/// is the same as the value read for reads.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AccessEvent {
+    pub access: BusAccess,
+    pub old_value: u8,
+}
+
+/// A bus with 64 KiB of flat RAM and nothing else, which records what the CPU
+/// does with it on every M-cycle.
+///
+/// This is for testing the CPU on its own, e.g. against per-instruction test
+/// vectors.
+pub struct TestBus {
+    memory: Box<[u8; 0x10000]>,
+    // The access made during the current M-cycle, if any.
+    access: Option<BusAccess>,
+    cycles: Vec<Option<BusAccess>>,
+}
+
+impl TestBus {
+    /// Creates a bus with all of its RAM set to 0.
+    pub fn new() -> Self {
+        Self { memory: Box::new([0; 0x10000]), access: None, cycles: Vec::new() }
+    }
+
+    pub fn memory(&self) -> &[u8; 0x10000] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8; 0x10000] {
+        &mut self.memory
+    }
+
+    /// Removes and returns the accesses made on every M-cycle since the last
+    /// call, in order, where None means the CPU didn't access the bus on that
+    /// cycle.
+    pub fn take_cycles(&mut self) -> Vec<Option<BusAccess>> {
+        std::mem::take(&mut self.cycles)
+    }
+
+    fn record(&mut self, access: BusAccess) {
+        // The CPU can only access the bus once per M-cycle.
+        debug_assert!(self.access.is_none(), "{:?} after {:?} in one M-cycle", access, self.access);
+        self.access = Some(access);
+    }
+}
+
+impl Default for TestBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus for TestBus {
+    fn read(&mut self, address: u16) -> Result<u8, EmulationError> {
+        let value = self.memory[address as usize];
+        self.record(BusAccess::Read { address, value });
+        Ok(value)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+        self.record(BusAccess::Write { address, value });
+    }
+
+    fn peek(&self, address: u16) -> Result<u8, EmulationError> {
+        Ok(self.memory[address as usize])
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+    }
+
+    fn tick(&mut self) {
+        let access = self.access.take();
+        self.cycles.push(access);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gb::{CpuState, Gameboy};
+
+    #[test]
+    fn test_bus_records_every_m_cycle() {
+        // LD (HL),$42.
+        let mut bus = TestBus::new();
+        bus.memory_mut()[0xC000..][..2].copy_from_slice(&[0x36, 0x42]);
+        let cpu = CpuState { pc: 0xC000, h: 0xD0, l: 0x10, ..CpuState::default() };
+        let mut gb = Gameboy::from_parts(cpu, bus);
+
+        gb.step_instruction().unwrap();
+        assert_eq!(gb.pc(), 0xC002);
+        assert_eq!(gb.bus().memory()[0xD010], 0x42);
+        assert_eq!(gb.bus_mut().take_cycles(), [
+            Some(BusAccess::Read { address: 0xC000, value: 0x36 }),
+            Some(BusAccess::Read { address: 0xC001, value: 0x42 }),
+            Some(BusAccess::Write { address: 0xD010, value: 0x42 }),
+        ]);
+        assert!(gb.bus_mut().take_cycles().is_empty());
+    }
+}
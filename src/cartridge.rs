@@ -0,0 +1,232 @@
+//! The cartridge: its ROM, and the memory bank controller (MBC) and RAM that
+//! bigger cartridges have.
+
+use std::ops::RangeInclusive;
+
+use crate::error::EmulationError;
+
+pub const ROM_START: u16 = 0x0000;
+pub const ROM_END: u16 = 0x7FFF;
+pub const RAM_START: u16 = 0xA000;
+pub const RAM_END: u16 = 0xBFFF;
+
+const ROM_BANK_LEN: usize = 0x4000;
+const RAM_BANK_LEN: usize = 0x2000;
+
+// The cartridge header, which says what hardware is on the cartridge.
+const CARTRIDGE_TYPE: usize = 0x0147;
+const RAM_SIZE: usize = 0x0149;
+const HEADER_CHECKSUM: usize = 0x014D;
+const HEADER_CHECKSUM_RANGE: RangeInclusive<usize> = 0x0134..=0x014C;
+
+// The cartridge types that are supported.
+const CARTRIDGE_ROM_ONLY: u8 = 0x00;
+const CARTRIDGE_MBC1: u8 = 0x01;
+const CARTRIDGE_MBC1_RAM: u8 = 0x02;
+// TODO: RAM isn't saved to a file, so the battery doesn't do anything yet.
+const CARTRIDGE_MBC1_RAM_BATTERY: u8 = 0x03;
+
+/// A cartridge with only ROM, or with an MBC1 and optionally RAM.
+pub struct Cartridge {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    mbc: Option<Mbc1>,
+}
+
+impl Cartridge {
+    /// ROMs without a valid cartridge header, such as small test programs, are
+    /// treated as if they only had ROM.
+    pub fn new(rom: Vec<u8>) -> Result<Self, EmulationError> {
+        let (mbc, ram_len) = match cartridge_type(&rom) {
+            Some(CARTRIDGE_ROM_ONLY) | None => (None, 0),
+            Some(CARTRIDGE_MBC1) => (Some(Mbc1::default()), 0),
+            Some(CARTRIDGE_MBC1_RAM | CARTRIDGE_MBC1_RAM_BATTERY) => {
+                (Some(Mbc1::default()), ram_len(rom[RAM_SIZE]))
+            },
+            Some(cartridge_type) => {
+                return Err(EmulationError::UnsupportedCartridge(cartridge_type))
+            },
+        };
+        Ok(Self { rom, ram: vec![0; ram_len], mbc })
+    }
+
+    pub fn rom_len(&self) -> usize {
+        self.rom.len()
+    }
+
+    /// Reads from ROM through the MBC, or returns None if that is past the end
+    /// of the ROM.
+    pub fn read_rom(&self, address: u16) -> Option<u8> {
+        self.rom.get(self.rom_offset(address)).copied()
+    }
+
+    /// Reads from cartridge RAM, which reads as 0xFF when there isn't any or it
+    /// is disabled.
+    pub fn read_ram(&self, address: u16) -> u8 {
+        self.ram_offset(address).map_or(0xFF, |offset| self.ram[offset])
+    }
+
+    /// Writes to cartridge RAM, or to the MBC's registers for addresses in ROM.
+    pub fn write(&mut self, address: u16, data: u8) {
+        match address {
+            ROM_START..=ROM_END => {
+                if let Some(mbc) = &mut self.mbc {
+                    mbc.write(address, data);
+                }
+            },
+            _ => {
+                if let Some(offset) = self.ram_offset(address) {
+                    self.ram[offset] = data;
+                }
+            },
+        }
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        let bank = match (&self.mbc, address) {
+            (None, _) => return address as usize,
+            (Some(mbc), 0x0000..=0x3FFF) if mbc.mode_1 => mbc.bank2 << 5,
+            (Some(_), 0x0000..=0x3FFF) => 0,
+            // Bank 0 can't be selected here, writing 0 selects bank 1 instead.
+            (Some(mbc), _) => mbc.bank2 << 5 | mbc.rom_bank.max(1),
+        };
+        // The MBC ignores the bits of the bank number that are past the size of the
+        // ROM, so banks past the end wrap around.
+        let bank = bank as usize % (self.rom.len() / ROM_BANK_LEN).max(1);
+        bank * ROM_BANK_LEN + (address as usize & (ROM_BANK_LEN - 1))
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        let mbc = self.mbc.as_ref().filter(|mbc| mbc.ram_enabled && !self.ram.is_empty())?;
+        let bank = if mbc.mode_1 { mbc.bank2 as usize } else { 0 };
+        // RAM smaller than a bank is mirrored across it.
+        Some((bank * RAM_BANK_LEN + (address as usize & (RAM_BANK_LEN - 1))) % self.ram.len())
+    }
+}
+
+// The registers of the MBC1, which are written to through ROM.
+#[derive(Default)]
+struct Mbc1 {
+    ram_enabled: bool,
+    // The lower 5 bits of the ROM bank mapped at 0x4000-0x7FFF.
+    rom_bank: u8,
+    // The upper 2 bits of the ROM bank, which in mode 1 are also the ROM bank
+    // mapped at 0x0000-0x3FFF (shifted the same way) and the RAM bank.
+    bank2: u8,
+    mode_1: bool,
+}
+
+impl Mbc1 {
+    fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = data & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = data & 0x1F,
+            0x4000..=0x5FFF => self.bank2 = data & 0x03,
+            _ => self.mode_1 = data & 0x01 != 0,
+        }
+    }
+}
+
+// The cartridge type from the header, or None if the ROM doesn't have a valid
+// header.
+fn cartridge_type(rom: &[u8]) -> Option<u8> {
+    let checksum = rom
+        .get(HEADER_CHECKSUM_RANGE)?
+        .iter()
+        .fold(0u8, |checksum, byte| checksum.wrapping_sub(*byte).wrapping_sub(1));
+    if checksum == *rom.get(HEADER_CHECKSUM)? {
+        Some(rom[CARTRIDGE_TYPE])
+    } else {
+        None
+    }
+}
+
+// The size of cartridge RAM from the code in the header.
+fn ram_len(code: u8) -> usize {
+    match code {
+        0x01 => 0x800,
+        0x02 => 0x2000,
+        0x03 => 0x8000,
+        0x04 => 0x20000,
+        0x05 => 0x10000,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // A ROM of `banks` banks with a valid header, where every byte is the number
+    // of its bank.
+    fn rom(cartridge_type: u8, ram_size: u8, banks: usize) -> Vec<u8> {
+        let mut rom: Vec<_> = (0..banks).flat_map(|bank| [bank as u8; ROM_BANK_LEN]).collect();
+        rom[CARTRIDGE_TYPE] = cartridge_type;
+        rom[RAM_SIZE] = ram_size;
+        rom[HEADER_CHECKSUM] = HEADER_CHECKSUM_RANGE
+            .map(|address| rom[address])
+            .fold(0u8, |checksum, byte| checksum.wrapping_sub(byte).wrapping_sub(1));
+        rom
+    }
+
+    #[test]
+    fn cartridge_type_is_checked_when_header_is_valid() {
+        let mut rom = vec![0; 0x8000];
+        // MBC3+RAM+BATTERY.
+        rom[CARTRIDGE_TYPE] = 0x13;
+        assert!(Cartridge::new(rom.clone()).is_ok());
+
+        rom[HEADER_CHECKSUM] = HEADER_CHECKSUM_RANGE
+            .map(|address| rom[address])
+            .fold(0u8, |checksum, byte| checksum.wrapping_sub(byte).wrapping_sub(1));
+        assert!(matches!(Cartridge::new(rom), Err(EmulationError::UnsupportedCartridge(0x13))));
+    }
+
+    #[test]
+    fn mbc1_switches_rom_banks() {
+        let mut cartridge = Cartridge::new(rom(CARTRIDGE_MBC1, 0, 128)).unwrap();
+        assert_eq!(cartridge.read_rom(0x0000), Some(0));
+        assert_eq!(cartridge.read_rom(0x4000), Some(1));
+
+        cartridge.write(0x2000, 0x05);
+        assert_eq!(cartridge.read_rom(0x7FFF), Some(5));
+        // Bank 0 selects bank 1 instead.
+        cartridge.write(0x2000, 0x00);
+        assert_eq!(cartridge.read_rom(0x4000), Some(1));
+
+        // The upper 2 bits.
+        cartridge.write(0x4000, 0x02);
+        cartridge.write(0x2000, 0x03);
+        assert_eq!(cartridge.read_rom(0x4000), Some(0x43));
+        assert_eq!(cartridge.read_rom(0x0000), Some(0));
+        cartridge.write(0x6000, 0x01);
+        assert_eq!(cartridge.read_rom(0x0000), Some(0x40));
+
+        // Banks past the end of the ROM wrap around.
+        let mut cartridge = Cartridge::new(rom(CARTRIDGE_MBC1, 0, 4)).unwrap();
+        cartridge.write(0x2000, 0x06);
+        assert_eq!(cartridge.read_rom(0x4000), Some(2));
+    }
+
+    #[test]
+    fn mbc1_ram_is_enabled_and_banked() {
+        // 32 KiB of RAM, in 4 banks.
+        let mut cartridge = Cartridge::new(rom(CARTRIDGE_MBC1_RAM, 0x03, 4)).unwrap();
+        cartridge.write(0xA000, 0x12);
+        assert_eq!(cartridge.read_ram(0xA000), 0xFF);
+
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x12);
+        assert_eq!(cartridge.read_ram(0xA000), 0x12);
+
+        cartridge.write(0x6000, 0x01);
+        cartridge.write(0x4000, 0x01);
+        assert_eq!(cartridge.read_ram(0xA000), 0x00);
+        cartridge.write(0xA000, 0x34);
+        cartridge.write(0x4000, 0x00);
+        assert_eq!(cartridge.read_ram(0xA000), 0x12);
+
+        cartridge.write(0x0000, 0x00);
+        assert_eq!(cartridge.read_ram(0xA000), 0xFF);
+    }
+}
@@ -24,11 +24,11 @@ impl CommandHistory {
         self.queue.push_back(value);
     }
 
-    pub fn iter<'a>(&'a self) -> vec_deque::Iter<'a, String> {
+    pub fn iter(&self) -> vec_deque::Iter<'_, String> {
         self.queue.iter()
     }
 
-    pub fn entry<'a>(&'a self, idx: usize) -> Option<&'a str> {
+    pub fn entry(&self, idx: usize) -> Option<&str> {
         self.queue.get(self.len() - 1 - idx).map(String::as_str)
     }
 
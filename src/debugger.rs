@@ -1,54 +1,167 @@
+use std::fmt;
 use std::io::{self, Write as _};
 
 use console::Term;
-use indexmap::IndexSet;
+use indexmap::{IndexMap, IndexSet};
 
+use self::call_stack::{is_call, is_return, CallStack, FrameKind};
+use self::condition::{Condition, ConditionError};
+pub use self::symbols::Symbols;
+use crate::bus::{AccessEvent, Bus, BusAccess};
 use crate::command_history::CommandHistory;
-use crate::gb::{Gameboy, Register};
+use crate::disasm::{self, Disassembly};
+use crate::error::EmulationError;
+use crate::gb::{CpuState, Gameboy, Register, Register16, Register8};
+use crate::screenshot::{self, Palette};
+
+// How many instructions "disasm" shows if it isn't told, and the most it
+// shows, which is enough for all of memory even if every instruction is a
+// single byte.
+const DEFAULT_DISASSEMBLY_COUNT: usize = 10;
+const MAX_DISASSEMBLY_COUNT: usize = 0x10000;
+
+// How many bytes "mem" shows if it isn't told, the most it shows, which is all
+// of memory, and how many it shows per line.
+const DEFAULT_DUMP_LEN: usize = 64;
+const MAX_DUMP_LEN: usize = 0x10000;
+const DUMP_BYTES_PER_LINE: usize = 16;
+
+// Makes the memory commands skip the side effects of reading or writing I/O
+// registers.
+const RAW_FLAG: &str = "--raw";
+
+mod call_stack;
+mod condition;
+mod symbols;
 
 pub struct Debugger {
     gameboy: Gameboy,
     command_history: CommandHistory,
 
-    // An IndexSet is used to preserve order, so "break-list" doesn't show breakpoints in an
+    // An IndexMap is used to preserve order, so "break-list" doesn't show breakpoints in an
     // arbitrary and inconsistent order.
-    breakpoints: IndexSet<u16>,
+    breakpoints: IndexMap<u16, Breakpoint>,
+    watchpoints: IndexSet<Watchpoint>,
+
+    // The return addresses the CPU has pushed, for "backtrace".
+    call_stack: CallStack,
+    // Names for addresses, if the game has a symbol file.
+    symbols: Symbols,
+
+    // The colors used by the "screenshot" command.
+    palette: Palette,
 }
 
 impl Debugger {
     pub fn new(gameboy: Gameboy) -> Self {
-        Self { gameboy, command_history: CommandHistory::new(10), breakpoints: IndexSet::new() }
+        Self {
+            call_stack: CallStack::new(gameboy.pc(), gameboy.sp()),
+            symbols: Symbols::default(),
+            gameboy,
+            command_history: CommandHistory::new(10),
+            breakpoints: IndexMap::new(),
+            watchpoints: IndexSet::new(),
+            palette: Palette::default(),
+        }
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
     }
 
     fn invoke_command(&mut self, command: &str) {
         match Command::parse(command) {
-            Ok(Command::BreakAdd(address)) => {
-                self.breakpoints.insert(address);
-                println!("breakpoint added @ {address:#X}");
+            Ok(Command::BreakAdd(address, breakpoint)) => {
+                println!("breakpoint added @ {address:#X}{breakpoint}");
+                self.breakpoints.insert(address, breakpoint);
             },
             Ok(Command::BreakRemove(address)) => {
-                self.breakpoints.retain(|bp| *bp != address);
+                self.breakpoints.retain(|bp, _| *bp != address);
                 println!("breakpoint(s) removed @ {address:#X}");
             },
             Ok(Command::BreakList) => {
-                self.breakpoints.iter().for_each(|bp| println!("{bp:#X}"));
+                self.breakpoints.iter().for_each(|(bp, breakpoint)| {
+                    println!("{bp:#X}{breakpoint} (hit {} time(s))", breakpoint.hits)
+                });
             },
-            Ok(Command::Continue) => loop {
-                self.gameboy.cycle();
-                if self.should_break() {
-                    break;
+            Ok(Command::Backtrace) => self.backtrace().iter().for_each(|line| println!("{line}")),
+            Ok(Command::Continue) => self.run_until(|_| false),
+            Ok(Command::Over) => {
+                self.print_next_instruction();
+                let pc = self.gameboy.pc();
+                let opcode = self.gameboy.read_memory(pc);
+                if self.gameboy.is_instruction_done() && is_call(opcode) {
+                    // Returning lands after the call with the return address popped,
+                    // unless the call was recursive.
+                    let (return_address, sp) =
+                        (self.disassemble(pc).next_address(), self.gameboy.sp());
+                    self.run_until(|gameboy| gameboy.pc() == return_address && gameboy.sp() >= sp);
+                } else {
+                    self.run_until(|_| true);
                 }
             },
-            Ok(Command::ContinueUntilNotImpl) => loop {
-                match self.gameboy.peek_instruction_state() {
-                    Ok(_) => self.gameboy.cycle(),
-                    Err(opcode) => {
-                        println!("not impl: 0x{opcode:02X}");
+            Ok(Command::Out) => {
+                // The return from the current function pops its return address, and
+                // so leaves SP above where it is now. Returns from functions it calls
+                // don't.
+                let sp = self.gameboy.sp();
+                self.run_until(|gameboy| {
+                    is_return(gameboy.read_memory(gameboy.instruction_address()))
+                        && gameboy.sp() > sp
+                });
+                self.print_next_instruction();
+            },
+            Ok(Command::Until(address)) => {
+                self.run_until(|gameboy| gameboy.pc() == address);
+                self.print_next_instruction();
+            },
+            Ok(Command::WatchAdd(watchpoint)) => {
+                self.watchpoints.insert(watchpoint);
+                println!("watchpoint added: {watchpoint}");
+            },
+            Ok(Command::WatchRemove { start, end }) => {
+                self.watchpoints
+                    .retain(|watchpoint| (watchpoint.start, watchpoint.end) != (start, end));
+                println!("watchpoint(s) removed @ {}", format_range(start, end));
+            },
+            Ok(Command::WatchList) => {
+                self.watchpoints.iter().for_each(|watchpoint| println!("{watchpoint}"));
+            },
+            Ok(Command::Disasm { address, count }) => {
+                let address = address.unwrap_or_else(|| self.gameboy.pc());
+                self.disassembly_listing(address, count).iter().for_each(|line| println!("{line}"));
+            },
+            Ok(Command::ContinueUntilNotImpl) => {
+                while !self.report_lockup() {
+                    if let Err(error) = self.cycle() {
+                        println!("{error}");
                         break;
-                    },
-                };
+                    }
+                }
+            },
+            Ok(Command::Mem { address, len, raw }) => {
+                self.memory_dump(address, len, raw).iter().for_each(|line| println!("{line}"));
+            },
+            Ok(Command::MemWrite { address, bytes, raw }) => {
+                for (offset, byte) in bytes.iter().enumerate() {
+                    self.write_memory(address.wrapping_add(offset as u16), *byte, raw);
+                }
+                println!("wrote {} byte(s) @ {address:#X}", bytes.len());
+            },
+            Ok(Command::MemFill { start, end, value, raw }) => {
+                for address in start..=end {
+                    self.write_memory(address, value, raw);
+                }
+                println!("filled {start:#X}-{end:#X} with {value:#04X}");
+            },
+            Ok(Command::Exit) => {
+                self.gameboy.flush_trace();
+                std::process::exit(0)
             },
-            Ok(Command::Exit) => std::process::exit(0),
             Ok(Command::Help) => {
                 #[rustfmt::skip]
                 println!(
@@ -56,16 +169,34 @@ r#"-------------
 Boyo Debugger
 -------------
 Commands
-* break-add <address> - Adds a new breakpoint at the given (hex) address.
-* break-list - Shows all the currently active breakpoints.
+* backtrace - Shows the calls that led to the current instruction, with symbols if there is a symbol file.
+* break-add <address> [if <condition>] [after <count>] - Adds a new breakpoint at the given (hex) address, replacing any that is already there.
+  It only stops when the condition is met, and not for the first count (decimal) times it is hit.
+  Conditions compare registers (a, bc, pc...), flags (zf, nf, hf, cf), memory ([hl], [$C000]) and numbers ($ or 0x for hex) with == != < <= > >=, and combine them with ! && || and parentheses.
+* break-list - Shows all the currently active breakpoints, and how many times they have been hit.
 * break-remove <address> - Removes an existing breakpoint at the given (hex) address, if it exists.
-* continue - Begins execution until a breakpoint is hit.
-* continue-until-not-impl - Begins execution until a non-implemented opcode is encountered.
+* continue - Begins execution until a breakpoint, watchpoint or LD B,B is hit or the cpu locks up.
+* continue-until-not-impl - Begins execution until a non-implemented opcode is encountered or the cpu locks up.
+* disasm [address] [count] - Disassembles count (default 10, at most 65536) instructions from the given (hex) address, or from PC. PC is marked with > and breakpoints with *.
 * exit - Exits the program.
+* mem <address> [length] - Shows length (default 64, at most 65536) bytes of memory from the given (hex) address, in hex and ASCII.
+* mem-fill <start> <end> <byte> - Writes the given (hex) byte to every (hex) address from start to end.
+* mem-write <address> <byte...> - Writes the given (hex) bytes to memory from the given (hex) address.
+  The memory commands read and write like the cpu does. With --raw, they don't have side effects, and I/O registers can't be written.
 * help - How you got here.
 * next - Displays the next instruction to be executed.
+* out - Executes until the current function returns.
+* over - Executes a single instruction, or a whole call if it is a CALL or RST.
 * registers - Displays the contents of all cpu registers.
-* step - Executes a single instruction."#
+* screenshot <path> - Saves the last frame to a PNG or PPM file (depending on the extension).
+* set <register> <value> - Sets a register (a-l, af, bc, de, hl, pc or sp) to the given (hex) value, or a flag (zf, nf, hf or cf) to 0 or 1. Shows the registers afterwards.
+* step - Executes a single instruction.
+* step-cycle - Executes a single M-cycle.
+* until <address> - Executes until PC reaches the given (hex) address.
+  Like continue, out, over and until stop early if a breakpoint, watchpoint or LD B,B is hit.
+* watch-add <read|write|access> <address>[-<end>] [byte] - Adds a watchpoint that stops continue when the given (hex) address or range is read, written or either, optionally only with the given (hex) byte.
+* watch-list - Shows all the currently active watchpoints.
+* watch-remove <address>[-<end>] - Removes the watchpoints on the given (hex) address or range, if there are any."#
                 );
             },
             Ok(Command::History) => {
@@ -79,9 +210,36 @@ Commands
                     .registers()
                     .for_each(|Register { name, value }| println!("{name}: {value:#X}"));
             },
+            Ok(Command::Screenshot(path)) => {
+                match screenshot::save(&path, self.gameboy.framebuffer(), self.palette) {
+                    Ok(()) => println!("screenshot saved to {path}"),
+                    Err(error) => eprintln!("failed to save screenshot: {error}"),
+                }
+            },
+            Ok(Command::Set { target, value }) => {
+                let cpu = self.gameboy.cpu_mut();
+                match target {
+                    SetTarget::Register8(register) => *cpu.register8_mut(register) = value as u8,
+                    SetTarget::Register16(register) => cpu.set_register16(register, value),
+                    SetTarget::Pc => cpu.pc = value,
+                    SetTarget::Sp => cpu.sp = value,
+                    SetTarget::Flag(flag) if value == 0 => cpu.f &= !flag.mask(),
+                    SetTarget::Flag(flag) => cpu.f |= flag.mask(),
+                }
+                self.gameboy
+                    .registers()
+                    .for_each(|Register { name, value }| println!("{name}: {value:#X}"));
+            },
             Ok(Command::Step) => {
                 self.print_next_instruction();
-                self.gameboy.cycle();
+                self.run_until(|_| true);
+            },
+            Ok(Command::StepCycle) => {
+                self.print_next_instruction();
+                if let Err(error) = self.cycle() {
+                    eprintln!("{error}");
+                }
+                self.report_lockup();
             },
             Err(error) => {
                 eprintln!("{error}");
@@ -90,15 +248,199 @@ Commands
         self.command_history.push(command.to_owned());
     }
 
+    // Runs a single M-cycle, keeping track of calls and returns.
+    fn cycle(&mut self) -> Result<(), EmulationError> {
+        self.gameboy.cycle()?;
+        if self.gameboy.is_instruction_done() {
+            let address = self.gameboy.instruction_address();
+            let opcode = self.gameboy.read_memory(address);
+            let (pc, sp) = (self.gameboy.pc(), self.gameboy.sp());
+            self.call_stack.instruction_done(address, opcode, pc, sp);
+        }
+        Ok(())
+    }
+
+    // The lines printed by the "backtrace" command: where the CPU is, then
+    // every call (or interrupt) that hasn't returned yet, innermost first.
+    fn backtrace(&self) -> Vec<String> {
+        let mut lines = vec![format!("#0 {}", self.describe(self.gameboy.pc()))];
+        for (index, frame) in self.call_stack.frames().iter().rev().enumerate() {
+            let action = match frame.kind {
+                FrameKind::Call => "called",
+                FrameKind::Interrupt => "interrupted by",
+            };
+            lines.push(format!(
+                "#{} {} {action} {}",
+                index + 1,
+                self.describe(frame.from),
+                self.describe(frame.to)
+            ));
+        }
+        if let Some(desync) = self.call_stack.desync() {
+            lines.push(format!("warning: the call stack may be wrong, since {desync}"));
+        }
+        lines
+    }
+
+    // An address along with its symbol, if it has one.
+    fn describe(&self, address: u16) -> String {
+        match self.symbols.name(address) {
+            Some(name) => format!("{address:#06X} ({name})"),
+            None => format!("{address:#06X}"),
+        }
+    }
+
+    // Runs until `done` returns true at the end of an instruction, or until
+    // emulation fails, the cpu locks up, or a breakpoint, watchpoint or LD B,B is
+    // hit.
+    fn run_until(&mut self, mut done: impl FnMut(&Gameboy) -> bool) {
+        // Only stop for an LD B,B executed or memory accessed from here on.
+        self.gameboy.take_software_breakpoint();
+        self.gameboy.bus_mut().take_access_event();
+        while !self.report_lockup() {
+            if let Err(error) = self.cycle() {
+                eprintln!("{error}");
+                break;
+            }
+            if let Some(address) = self.gameboy.take_software_breakpoint() {
+                println!("software breakpoint (LD B,B) hit @ {address:#X}");
+                break;
+            }
+            let event = self.gameboy.bus_mut().take_access_event();
+            if let Some(hit) = event.and_then(|event| self.watchpoint_hit(event)) {
+                println!("{hit}");
+                break;
+            }
+            if !self.gameboy.is_instruction_done() {
+                continue;
+            }
+            if self.should_break() {
+                println!("breakpoint hit @ {:#X}", self.gameboy.pc());
+                break;
+            }
+            if done(&self.gameboy) {
+                break;
+            }
+        }
+    }
+
     fn print_next_instruction(&self) {
         match self.gameboy.peek_instruction_state() {
-            Ok(state) => println!("{state:?}"),
-            Err(opcode) => println!("0x{opcode:02X}"),
+            Ok(state) => println!(
+                "{} (M-cycle {}/{})",
+                self.disassemble(state.address),
+                state.m_cycle,
+                state.instruction.cycles()
+            ),
+            Err(error) => {
+                println!("{}", self.disassemble(self.gameboy.pc()));
+                println!("{error}");
+            },
+        };
+    }
+
+    // The lines printed by the "mem" command: the address, 16 bytes in hex and
+    // the same bytes in ASCII.
+    fn memory_dump(&mut self, address: u16, len: usize, raw: bool) -> Vec<String> {
+        let mut bytes = Vec::with_capacity(len);
+        for offset in 0..len {
+            let address = address.wrapping_add(offset as u16);
+            let byte = if raw {
+                self.gameboy.read_memory(address)
+            } else {
+                self.gameboy.bus_mut().read(address).unwrap_or(0xFF)
+            };
+            bytes.push(byte);
+        }
+
+        let mut lines = Vec::new();
+        for (index, chunk) in bytes.chunks(DUMP_BYTES_PER_LINE).enumerate() {
+            let start = address.wrapping_add((index * DUMP_BYTES_PER_LINE) as u16);
+            let hex: Vec<_> = chunk.iter().map(|byte| format!("{byte:02X}")).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(
+                    |byte| {
+                        if byte.is_ascii_graphic() || *byte == b' ' {
+                            *byte as char
+                        } else {
+                            '.'
+                        }
+                    },
+                )
+                .collect();
+            lines.push(format!(
+                "{start:04X}  {:<width$}  |{ascii}|",
+                hex.join(" "),
+                width = DUMP_BYTES_PER_LINE * 3 - 1
+            ));
+        }
+        lines
+    }
+
+    fn write_memory(&mut self, address: u16, value: u8, raw: bool) {
+        if raw {
+            self.gameboy.poke_memory(address, value);
+        } else {
+            self.gameboy.write_memory(address, value);
+        }
+    }
+
+    fn disassemble(&self, address: u16) -> Disassembly {
+        disasm::disassemble(address, |address| self.gameboy.read_memory(address))
+    }
+
+    // The lines printed by the "disasm" command.
+    fn disassembly_listing(&self, mut address: u16, count: usize) -> Vec<String> {
+        let mut lines = Vec::with_capacity(count);
+        for _ in 0..count {
+            let instruction = self.disassemble(address);
+            let pc = if address == self.gameboy.pc() { '>' } else { ' ' };
+            let breakpoint = if self.breakpoints.contains_key(&address) { '*' } else { ' ' };
+            lines.push(format!("{pc}{breakpoint} {instruction}"));
+            address = instruction.next_address();
+        }
+        lines
+    }
+
+    // Running a locked up CPU would never hit a breakpoint, so commands that
+    // run until something happens stop once it locks up. Returns whether it
+    // has.
+    fn report_lockup(&self) -> bool {
+        match self.gameboy.lockup() {
+            Some(lockup) => {
+                println!("{lockup}");
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Describes the access if any watchpoint is hit by it.
+    fn watchpoint_hit(&self, event: AccessEvent) -> Option<String> {
+        let watchpoint =
+            self.watchpoints.iter().find(|watchpoint| watchpoint.matches(event.access))?;
+        let pc = self.gameboy.instruction_address();
+        let access = match event.access {
+            BusAccess::Read { address, value } => format!("read {value:#04X} from {address:#X}"),
+            BusAccess::Write { address, value } => {
+                format!("wrote {value:#04X} to {address:#X} (was {:#04X})", event.old_value)
+            },
         };
+        Some(format!("watchpoint ({watchpoint}) hit @ {pc:#X}: {access}"))
     }
 
-    fn should_break(&self) -> bool {
-        self.breakpoints.contains(&self.gameboy.pc())
+    // Counts a hit if there is a breakpoint at PC and its condition is met.
+    fn should_break(&mut self) -> bool {
+        let gameboy = &self.gameboy;
+        let Some(breakpoint) = self.breakpoints.get_mut(&gameboy.pc()) else {
+            return false;
+        };
+        if breakpoint.condition.as_ref().is_some_and(|condition| !condition.is_met(gameboy)) {
+            return false;
+        }
+        breakpoint.hits += 1;
+        breakpoint.hits > breakpoint.after
     }
 }
 
@@ -166,64 +508,372 @@ fn update_command_display(debugger: &Debugger, term: &Term, history_idx: usize,
 
 #[derive(Debug, Eq, PartialEq)]
 enum Command {
-    BreakAdd(u16),
+    Backtrace,
+    BreakAdd(u16, Breakpoint),
     BreakList,
     BreakRemove(u16),
     Continue,
     ContinueUntilNotImpl,
+    Disasm { address: Option<u16>, count: usize },
     Exit,
     Help,
     History,
+    Mem { address: u16, len: usize, raw: bool },
+    MemFill { start: u16, end: u16, value: u8, raw: bool },
+    MemWrite { address: u16, bytes: Vec<u8>, raw: bool },
     Next,
     Registers,
+    Screenshot(String),
+    Set { target: SetTarget, value: u16 },
+    Out,
+    Over,
     Step,
+    StepCycle,
+    Until(u16),
+    WatchAdd(Watchpoint),
+    WatchList,
+    WatchRemove { start: u16, end: u16 },
+}
+
+/// Stops "continue" when memory in a range is accessed.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct Watchpoint {
+    kind: WatchKind,
+    start: u16,
+    end: u16,
+    // Only accesses of this value are watched, if it is set.
+    value: Option<u8>,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: BusAccess) -> bool {
+        let kind = match access {
+            BusAccess::Read { .. } => self.kind != WatchKind::Write,
+            BusAccess::Write { .. } => self.kind != WatchKind::Read,
+        };
+        kind && (self.start..=self.end).contains(&access.address())
+            && self.value.is_none_or(|value| value == access.value())
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind, format_range(self.start, self.end))?;
+        if let Some(value) = self.value {
+            write!(f, " = {value:#04X}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum WatchKind {
+    Read,
+    Write,
+    // Either a read or a write.
+    Access,
+}
+
+impl WatchKind {
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "read" => Some(Self::Read),
+            "write" => Some(Self::Write),
+            "access" => Some(Self::Access),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Access => "access",
+        };
+        f.write_str(kind)
+    }
+}
+
+/// Stops "continue" at an address, once it has been hit `after` times with the
+/// condition met.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct Breakpoint {
+    condition: Option<Condition>,
+    after: u32,
+    hits: u32,
+}
+
+// Shows the options of the breakpoint the way they are given to "break-add".
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(condition) = &self.condition {
+            write!(f, " if {condition}")?;
+        }
+        if self.after > 0 {
+            write!(f, " after {}", self.after)?;
+        }
+        Ok(())
+    }
+}
+
+/// What the "set" command can change, and conditions can read.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum SetTarget {
+    Register8(Register8),
+    Register16(Register16),
+    Pc,
+    Sp,
+    Flag(Flag),
+}
+
+impl SetTarget {
+    fn parse(name: &str) -> Option<Self> {
+        let target = match name.to_ascii_lowercase().as_str() {
+            "a" => Self::Register8(Register8::A),
+            "b" => Self::Register8(Register8::B),
+            "c" => Self::Register8(Register8::C),
+            "d" => Self::Register8(Register8::D),
+            "e" => Self::Register8(Register8::E),
+            "f" => Self::Register8(Register8::F),
+            "h" => Self::Register8(Register8::H),
+            "l" => Self::Register8(Register8::L),
+            "af" => Self::Register16(Register16::AF),
+            "bc" => Self::Register16(Register16::BC),
+            "de" => Self::Register16(Register16::DE),
+            "hl" => Self::Register16(Register16::HL),
+            "pc" => Self::Pc,
+            "sp" => Self::Sp,
+            "zf" => Self::Flag(Flag::Zero),
+            "nf" => Self::Flag(Flag::Subtract),
+            "hf" => Self::Flag(Flag::HalfCarry),
+            "cf" => Self::Flag(Flag::Carry),
+            _ => return None,
+        };
+        Some(target)
+    }
+
+    fn read(self, cpu: &CpuState) -> u16 {
+        match self {
+            Self::Register8(register) => cpu.register8(register) as u16,
+            Self::Register16(register) => cpu.register16(register),
+            Self::Pc => cpu.pc,
+            Self::Sp => cpu.sp,
+            Self::Flag(flag) => (cpu.f & flag.mask() != 0) as u16,
+        }
+    }
+
+    // The largest value that can be set.
+    fn max(self) -> u16 {
+        match self {
+            Self::Register8(_) => 0xFF,
+            Self::Register16(_) | Self::Pc | Self::Sp => 0xFFFF,
+            Self::Flag(_) => 1,
+        }
+    }
+
+    // The bits of a value that would go in the lower 4 bits of F, which don't
+    // exist and so have to be 0.
+    fn missing_bits(self) -> u16 {
+        match self {
+            Self::Register8(Register8::F) | Self::Register16(Register16::AF) => 0x0F,
+            _ => 0,
+        }
+    }
+}
+
+/// The flags in the upper 4 bits of F.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Flag {
+    Zero,
+    Subtract,
+    HalfCarry,
+    Carry,
+}
+
+impl Flag {
+    fn mask(self) -> u8 {
+        match self {
+            Self::Zero => 1 << 7,
+            Self::Subtract => 1 << 6,
+            Self::HalfCarry => 1 << 5,
+            Self::Carry => 1 << 4,
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
 enum CommandParseError<'a> {
     #[error("invalid command: {0}")]
     InvalidCommand(&'a str),
     #[error("invalid format")]
     InvalidFormat,
-    #[error("invalid breakpoint address")]
-    InvalidBreakpointAddress,
+    #[error("invalid address")]
+    InvalidAddress,
+    #[error("invalid count")]
+    InvalidCount,
+    #[error("invalid byte")]
+    InvalidByte,
+    #[error("unknown register or flag: {0}")]
+    InvalidRegister(&'a str),
+    #[error("{value} is out of range for {register}, which can be set to 0-{max:X}")]
+    InvalidRegisterValue { register: &'a str, value: &'a str, max: u16 },
+    #[error("{value} can't be put in {register}, since the lower 4 bits of F are always 0")]
+    InvalidFlagsValue { register: &'a str, value: &'a str },
+    #[error("the start of the range is after the end")]
+    InvalidRange,
+    #[error("invalid condition: {0}")]
+    InvalidCondition(#[from] ConditionError),
+    #[error("invalid watchpoint kind (expected read, write or access): {0}")]
+    InvalidWatchKind(&'a str),
 }
 
 impl Command {
     fn parse<'a>(s: &'a str) -> Result<Self, CommandParseError<'a>> {
         let tokens: Vec<_> = s.split(" ").collect();
         match tokens[0] {
-            "break-add" if tokens.len() == 2 => {
-                let address = parse_hex_address(&tokens[1])?;
-                Ok(Command::BreakAdd(address))
+            "break-add" if tokens.len() >= 2 => {
+                let address = parse_hex_address(tokens[1])?;
+                let mut breakpoint = Breakpoint::default();
+                let mut options = &tokens[2..];
+                if let [rest @ .., "after", count] = options {
+                    breakpoint.after =
+                        count.parse().map_err(|_| CommandParseError::InvalidCount)?;
+                    options = rest;
+                }
+                match options {
+                    [] => {},
+                    ["if", condition @ ..] => {
+                        breakpoint.condition = Some(Condition::parse(&condition.join(" "))?)
+                    },
+                    _ => return Err(CommandParseError::InvalidFormat),
+                }
+                Ok(Command::BreakAdd(address, breakpoint))
             },
             "break-remove" if tokens.len() == 2 => {
-                let address = parse_hex_address(&tokens[1])?;
+                let address = parse_hex_address(tokens[1])?;
                 Ok(Command::BreakRemove(address))
             },
+            "backtrace" if tokens.len() == 1 => Ok(Command::Backtrace),
             "break-list" if tokens.len() == 1 => Ok(Command::BreakList),
             "continue" if tokens.len() == 1 => Ok(Command::Continue),
             "continue-until-not-impl" if tokens.len() == 1 => Ok(Command::ContinueUntilNotImpl),
+            "disasm" if tokens.len() <= 3 => {
+                let address =
+                    tokens.get(1).map(|address| parse_hex_address(address)).transpose()?;
+                let count = match tokens.get(2) {
+                    Some(count) => count
+                        .parse()
+                        .ok()
+                        .filter(|count| *count <= MAX_DISASSEMBLY_COUNT)
+                        .ok_or(CommandParseError::InvalidCount)?,
+                    None => DEFAULT_DISASSEMBLY_COUNT,
+                };
+                Ok(Command::Disasm { address, count })
+            },
             "exit" if tokens.len() == 1 => Ok(Command::Exit),
             "help" if tokens.len() == 1 => Ok(Command::Help),
             "history" if tokens.len() == 1 => Ok(Command::History),
+            "mem" | "mem-fill" | "mem-write" => {
+                let raw = tokens.contains(&RAW_FLAG);
+                let args: Vec<_> = tokens[1..].iter().filter(|token| **token != RAW_FLAG).collect();
+                match (tokens[0], args.as_slice()) {
+                    ("mem", [address]) => {
+                        let address = parse_hex_address(address)?;
+                        Ok(Command::Mem { address, len: DEFAULT_DUMP_LEN, raw })
+                    },
+                    ("mem", [address, len]) => {
+                        let address = parse_hex_address(address)?;
+                        let len = len
+                            .parse()
+                            .ok()
+                            .filter(|len| *len <= MAX_DUMP_LEN)
+                            .ok_or(CommandParseError::InvalidCount)?;
+                        Ok(Command::Mem { address, len, raw })
+                    },
+                    ("mem-fill", [start, end, value]) => {
+                        let (start, end) = (parse_hex_address(start)?, parse_hex_address(end)?);
+                        if start > end {
+                            return Err(CommandParseError::InvalidRange);
+                        }
+                        Ok(Command::MemFill { start, end, value: parse_hex_byte(value)?, raw })
+                    },
+                    ("mem-write", [address, bytes @ ..]) if !bytes.is_empty() => {
+                        let address = parse_hex_address(address)?;
+                        let bytes = bytes
+                            .iter()
+                            .map(|byte| parse_hex_byte(byte))
+                            .collect::<Result<_, _>>()?;
+                        Ok(Command::MemWrite { address, bytes, raw })
+                    },
+                    _ => Err(CommandParseError::InvalidFormat),
+                }
+            },
             "next" if tokens.len() == 1 => Ok(Command::Next),
             "registers" if tokens.len() == 1 => Ok(Command::Registers),
+            // Everything after the command is the path, which can have spaces in it.
+            "screenshot" if tokens.len() >= 2 => match s.strip_prefix("screenshot ") {
+                Some(path) if !path.is_empty() => Ok(Command::Screenshot(path.to_owned())),
+                _ => Err(CommandParseError::InvalidFormat),
+            },
+            "set" if tokens.len() == 3 => {
+                let (register, value) = (tokens[1], tokens[2]);
+                let target = SetTarget::parse(register)
+                    .ok_or(CommandParseError::InvalidRegister(register))?;
+                let max = target.max();
+                let parsed = u16::from_str_radix(value.strip_prefix("0x").unwrap_or(value), 16)
+                    .ok()
+                    .filter(|parsed| *parsed <= max)
+                    .ok_or(CommandParseError::InvalidRegisterValue { register, value, max })?;
+                if parsed & target.missing_bits() != 0 {
+                    return Err(CommandParseError::InvalidFlagsValue { register, value });
+                }
+                Ok(Command::Set { target, value: parsed })
+            },
+            "out" if tokens.len() == 1 => Ok(Command::Out),
+            "over" if tokens.len() == 1 => Ok(Command::Over),
             "step" if tokens.len() == 1 => Ok(Command::Step),
+            "step-cycle" if tokens.len() == 1 => Ok(Command::StepCycle),
+            "until" if tokens.len() == 2 => Ok(Command::Until(parse_hex_address(tokens[1])?)),
+            "watch-add" if (3..=4).contains(&tokens.len()) => {
+                let kind = WatchKind::parse(tokens[1])
+                    .ok_or(CommandParseError::InvalidWatchKind(tokens[1]))?;
+                let (start, end) = parse_hex_range(tokens[2])?;
+                let value = tokens.get(3).map(|value| parse_hex_byte(value)).transpose()?;
+                Ok(Command::WatchAdd(Watchpoint { kind, start, end, value }))
+            },
+            "watch-list" if tokens.len() == 1 => Ok(Command::WatchList),
+            "watch-remove" if tokens.len() == 2 => {
+                let (start, end) = parse_hex_range(tokens[1])?;
+                Ok(Command::WatchRemove { start, end })
+            },
 
             // Valid commands should be enumerated here as a fall-through case in scenarios where an
             // invalid number of tokens are provided.
-            "break-add"
+            "backtrace"
+            | "break-add"
             | "break-remove"
             | "break-list"
             | "continue"
             | "continue-until-not-impl"
+            | "disasm"
             | "exit"
             | "help"
             | "history"
             | "next"
             | "registers"
-            | "step" => Err(CommandParseError::InvalidFormat),
+            | "screenshot"
+            | "set"
+            | "out"
+            | "over"
+            | "step"
+            | "step-cycle"
+            | "until"
+            | "watch-add"
+            | "watch-list"
+            | "watch-remove" => Err(CommandParseError::InvalidFormat),
 
             other => Err(CommandParseError::InvalidCommand(other)),
         }
@@ -232,23 +882,431 @@ impl Command {
 
 fn parse_hex_address(address: &str) -> Result<u16, CommandParseError<'_>> {
     let address = address.strip_prefix("0x").unwrap_or(address);
-    u16::from_str_radix(address, 16).map_err(|_| CommandParseError::InvalidBreakpointAddress)
+    u16::from_str_radix(address, 16).map_err(|_| CommandParseError::InvalidAddress)
+}
+
+// Parses either a single (hex) address, or a range of them like "C000-C0FF"
+// that includes both ends.
+fn parse_hex_range(range: &str) -> Result<(u16, u16), CommandParseError<'_>> {
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (parse_hex_address(start)?, parse_hex_address(end)?),
+        None => {
+            let address = parse_hex_address(range)?;
+            (address, address)
+        },
+    };
+    if start > end {
+        return Err(CommandParseError::InvalidRange);
+    }
+    Ok((start, end))
+}
+
+fn format_range(start: u16, end: u16) -> String {
+    if start == end {
+        format!("{start:#X}")
+    } else {
+        format!("{start:#X}-{end:#X}")
+    }
+}
+
+fn parse_hex_byte(byte: &str) -> Result<u8, CommandParseError<'_>> {
+    let byte = byte.strip_prefix("0x").unwrap_or(byte);
+    u8::from_str_radix(byte, 16).map_err(|_| CommandParseError::InvalidByte)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::gb::INITIAL_PC;
 
     #[test]
     fn parse_breakpoint_commands() {
-        assert_eq!(Command::parse("break-add 0xFFFF").unwrap(), Command::BreakAdd(0xFFFF));
-        assert_eq!(Command::parse("break-add FFFF").unwrap(), Command::BreakAdd(0xFFFF));
-        assert_eq!(Command::parse("break-add 0x0").unwrap(), Command::BreakAdd(0));
-        assert_eq!(Command::parse("break-add 0").unwrap(), Command::BreakAdd(0));
+        let breakpoint = Breakpoint::default();
+        assert_eq!(
+            Command::parse("break-add 0xFFFF").unwrap(),
+            Command::BreakAdd(0xFFFF, breakpoint.clone())
+        );
+        assert_eq!(
+            Command::parse("break-add FFFF").unwrap(),
+            Command::BreakAdd(0xFFFF, breakpoint.clone())
+        );
+        assert_eq!(
+            Command::parse("break-add 0x0").unwrap(),
+            Command::BreakAdd(0, breakpoint.clone())
+        );
+        assert_eq!(Command::parse("break-add 0").unwrap(), Command::BreakAdd(0, breakpoint));
 
         assert_eq!(Command::parse("break-remove 0xFFFF").unwrap(), Command::BreakRemove(0xFFFF));
         assert_eq!(Command::parse("break-remove FFFF").unwrap(), Command::BreakRemove(0xFFFF));
         assert_eq!(Command::parse("break-remove 0x0").unwrap(), Command::BreakRemove(0));
         assert_eq!(Command::parse("break-remove 0").unwrap(), Command::BreakRemove(0));
     }
+
+    #[test]
+    fn parse_disasm_command() {
+        assert_eq!(Command::parse("disasm").unwrap(), Command::Disasm {
+            address: None,
+            count: DEFAULT_DISASSEMBLY_COUNT
+        });
+        assert_eq!(Command::parse("disasm 0x150").unwrap(), Command::Disasm {
+            address: Some(0x150),
+            count: DEFAULT_DISASSEMBLY_COUNT
+        });
+        assert_eq!(Command::parse("disasm 150 3").unwrap(), Command::Disasm {
+            address: Some(0x150),
+            count: 3
+        });
+        assert!(matches!(Command::parse("disasm xyz"), Err(CommandParseError::InvalidAddress)));
+        assert!(matches!(Command::parse("disasm 150 -1"), Err(CommandParseError::InvalidCount)));
+        assert_eq!(Command::parse("disasm 0 65536").unwrap(), Command::Disasm {
+            address: Some(0),
+            count: MAX_DISASSEMBLY_COUNT
+        });
+        assert!(matches!(Command::parse("disasm 0 65537"), Err(CommandParseError::InvalidCount)));
+        assert!(matches!(
+            Command::parse("disasm 0 99999999999"),
+            Err(CommandParseError::InvalidCount)
+        ));
+    }
+
+    #[test]
+    fn disassembly_listing_marks_pc_and_breakpoints() {
+        // LD B,$12, JP $0100.
+        let mut rom = vec![0; INITIAL_PC as usize];
+        rom.extend([0x06, 0x12, 0xC3, 0x00, 0x01]);
+        let mut debugger = Debugger::new(Gameboy::new(rom).unwrap());
+        debugger.breakpoints.insert(0x0102, Breakpoint::default());
+
+        assert_eq!(debugger.disassembly_listing(INITIAL_PC, 2), [
+            ">  0100  06 12     LD B,$12",
+            " * 0102  C3 00 01  JP $0100",
+        ]);
+    }
+
+    #[test]
+    fn parse_memory_commands() {
+        assert_eq!(Command::parse("mem C000").unwrap(), Command::Mem {
+            address: 0xC000,
+            len: DEFAULT_DUMP_LEN,
+            raw: false
+        });
+        assert_eq!(Command::parse("mem --raw 0xFF00 128").unwrap(), Command::Mem {
+            address: 0xFF00,
+            len: 128,
+            raw: true
+        });
+        assert_eq!(Command::parse("mem 0 65536").unwrap(), Command::Mem {
+            address: 0,
+            len: MAX_DUMP_LEN,
+            raw: false
+        });
+        assert!(matches!(Command::parse("mem 0 65537"), Err(CommandParseError::InvalidCount)));
+        assert!(matches!(
+            Command::parse("mem 0 99999999999"),
+            Err(CommandParseError::InvalidCount)
+        ));
+        assert_eq!(Command::parse("mem-write 8000 01 0x02 ff").unwrap(), Command::MemWrite {
+            address: 0x8000,
+            bytes: vec![0x01, 0x02, 0xFF],
+            raw: false
+        });
+        assert_eq!(Command::parse("mem-fill 8000 80FF 00 --raw").unwrap(), Command::MemFill {
+            start: 0x8000,
+            end: 0x80FF,
+            value: 0x00,
+            raw: true
+        });
+        assert!(matches!(Command::parse("mem-write 8000"), Err(CommandParseError::InvalidFormat)));
+        assert!(matches!(
+            Command::parse("mem-write 8000 100"),
+            Err(CommandParseError::InvalidByte)
+        ));
+        assert!(matches!(
+            Command::parse("mem-fill 80FF 8000 00"),
+            Err(CommandParseError::InvalidRange)
+        ));
+    }
+
+    #[test]
+    fn memory_dump_shows_hex_and_ascii() {
+        let mut debugger = Debugger::new(Gameboy::new(Vec::new()).unwrap());
+        for (offset, byte) in b"Hello, boyo!\x00\x01".iter().enumerate() {
+            debugger.write_memory(0x8000 + offset as u16, *byte, false);
+        }
+
+        assert_eq!(debugger.memory_dump(0x8000, 18, true), [
+            "8000  48 65 6C 6C 6F 2C 20 62 6F 79 6F 21 00 01 00 00  |Hello, boyo!....|",
+            "8010  00 00                                            |..|",
+        ]);
+    }
+
+    #[test]
+    fn parse_screenshot_command() {
+        assert_eq!(
+            Command::parse("screenshot shot.png").unwrap(),
+            Command::Screenshot("shot.png".to_owned())
+        );
+        assert_eq!(
+            Command::parse("screenshot my shots/a  b.png").unwrap(),
+            Command::Screenshot("my shots/a  b.png".to_owned())
+        );
+        assert!(matches!(Command::parse("screenshot"), Err(CommandParseError::InvalidFormat)));
+        assert!(matches!(Command::parse("screenshot "), Err(CommandParseError::InvalidFormat)));
+    }
+
+    #[test]
+    fn parse_set_command() {
+        assert_eq!(Command::parse("set a 0x12").unwrap(), Command::Set {
+            target: SetTarget::Register8(Register8::A),
+            value: 0x12
+        });
+        assert_eq!(Command::parse("set HL C000").unwrap(), Command::Set {
+            target: SetTarget::Register16(Register16::HL),
+            value: 0xC000
+        });
+        assert_eq!(Command::parse("set zf 1").unwrap(), Command::Set {
+            target: SetTarget::Flag(Flag::Zero),
+            value: 1
+        });
+        assert!(matches!(Command::parse("set x 1"), Err(CommandParseError::InvalidRegister("x"))));
+        assert!(matches!(
+            Command::parse("set a 100"),
+            Err(CommandParseError::InvalidRegisterValue { max: 0xFF, .. })
+        ));
+        assert!(matches!(
+            Command::parse("set cf 2"),
+            Err(CommandParseError::InvalidRegisterValue { .. })
+        ));
+        assert_eq!(Command::parse("set af 12F0").unwrap(), Command::Set {
+            target: SetTarget::Register16(Register16::AF),
+            value: 0x12F0
+        });
+        assert!(matches!(
+            Command::parse("set f 0x0F"),
+            Err(CommandParseError::InvalidFlagsValue { .. })
+        ));
+        assert_eq!(
+            Command::parse("set af 12FF").unwrap_err().to_string(),
+            "12FF can't be put in af, since the lower 4 bits of F are always 0"
+        );
+        assert!(matches!(
+            Command::parse("set f 100"),
+            Err(CommandParseError::InvalidRegisterValue { max: 0xFF, .. })
+        ));
+    }
+
+    #[test]
+    fn set_changes_registers_and_flags() {
+        let mut debugger = Debugger::new(Gameboy::new(Vec::new()).unwrap());
+        debugger.invoke_command("set bc 1234");
+        debugger.invoke_command("set pc 150");
+        debugger.invoke_command("set f 0x50");
+        debugger.invoke_command("set zf 1");
+        debugger.invoke_command("set cf 0");
+
+        let cpu = debugger.gameboy.cpu();
+        assert_eq!((cpu.b, cpu.c, cpu.pc), (0x12, 0x34, 0x0150));
+        assert_eq!(cpu.f, 0xC0);
+    }
+
+    #[test]
+    fn parse_watchpoint_commands() {
+        assert_eq!(
+            Command::parse("watch-add write C000-C0FF 42").unwrap(),
+            Command::WatchAdd(Watchpoint {
+                kind: WatchKind::Write,
+                start: 0xC000,
+                end: 0xC0FF,
+                value: Some(0x42)
+            })
+        );
+        assert_eq!(
+            Command::parse("watch-add access 0xFF44").unwrap(),
+            Command::WatchAdd(Watchpoint {
+                kind: WatchKind::Access,
+                start: 0xFF44,
+                end: 0xFF44,
+                value: None
+            })
+        );
+        assert_eq!(Command::parse("watch-remove FF44").unwrap(), Command::WatchRemove {
+            start: 0xFF44,
+            end: 0xFF44
+        });
+        assert!(matches!(
+            Command::parse("watch-add peek C000"),
+            Err(CommandParseError::InvalidWatchKind("peek"))
+        ));
+        assert!(matches!(
+            Command::parse("watch-add read C0FF-C000"),
+            Err(CommandParseError::InvalidRange)
+        ));
+        assert!(matches!(Command::parse("watch-add read"), Err(CommandParseError::InvalidFormat)));
+    }
+
+    #[test]
+    fn watchpoint_stops_continue() {
+        // NOP, LD (HL),$42, then NOPs.
+        let mut rom = vec![0; 0x8000];
+        rom[INITIAL_PC as usize..][..3].copy_from_slice(&[0x00, 0x36, 0x42]);
+        let mut debugger = Debugger::new(Gameboy::new(rom).unwrap());
+        debugger.gameboy.cpu_mut().set_register16(Register16::HL, 0x8000);
+        debugger.breakpoints.insert(0x0110, Breakpoint::default());
+
+        // Neither the read of $42 nor a write of another value stops it.
+        debugger.invoke_command("watch-add read 8000-80FF");
+        debugger.invoke_command("watch-add write 8000 41");
+        let event =
+            AccessEvent { access: BusAccess::Write { address: 0x8000, value: 0x42 }, old_value: 0 };
+        assert!(debugger.watchpoint_hit(event).is_none());
+
+        debugger.invoke_command("watch-add write 8000 42");
+        debugger.invoke_command("continue");
+        assert_eq!(debugger.gameboy.instruction_address(), INITIAL_PC + 1);
+        assert_eq!(debugger.gameboy.read_memory(0x8000), 0x42);
+        assert_eq!(
+            debugger.watchpoint_hit(event).unwrap(),
+            "watchpoint (write 0x8000 = 0x42) hit @ 0x101: wrote 0x42 to 0x8000 (was 0x00)"
+        );
+
+        debugger.invoke_command("watch-remove 8000");
+        debugger.invoke_command("continue");
+        assert_eq!(debugger.gameboy.pc(), 0x0110);
+    }
+
+    #[test]
+    fn parse_conditional_breakpoints() {
+        let Command::BreakAdd(0x0150, breakpoint) =
+            Command::parse("break-add 150 if [HL] == $FF && !zf after 5").unwrap()
+        else {
+            panic!("not a breakpoint at 0x150");
+        };
+        assert_eq!(breakpoint.after, 5);
+        assert_eq!(breakpoint.to_string(), " if [HL] == $FF && !zf after 5");
+
+        let Command::BreakAdd(_, breakpoint) = Command::parse("break-add 150 after 2").unwrap()
+        else {
+            panic!("not a breakpoint");
+        };
+        assert_eq!((breakpoint.condition, breakpoint.after), (None, 2));
+
+        assert!(matches!(
+            Command::parse("break-add 150 if a =="),
+            Err(CommandParseError::InvalidCondition(_))
+        ));
+        assert!(matches!(
+            Command::parse("break-add 150 after many"),
+            Err(CommandParseError::InvalidCount)
+        ));
+        assert!(matches!(
+            Command::parse("break-add 150 when a == 1"),
+            Err(CommandParseError::InvalidFormat)
+        ));
+    }
+
+    #[test]
+    fn conditional_breakpoints_count_hits() {
+        let mut debugger = Debugger::new(Gameboy::new(Vec::new()).unwrap());
+        debugger.invoke_command("break-add 150 if a == $10 after 2");
+        debugger.gameboy.cpu_mut().pc = 0x0150;
+
+        assert!(!debugger.should_break());
+        debugger.gameboy.cpu_mut().a = 0x10;
+        assert!(!debugger.should_break());
+        assert!(!debugger.should_break());
+        assert!(debugger.should_break());
+        assert!(debugger.should_break());
+        assert_eq!(debugger.breakpoints[&0x0150].hits, 4);
+    }
+
+    #[test]
+    fn continue_skips_breakpoints_whose_condition_is_not_met() {
+        // NOPs.
+        let mut debugger = Debugger::new(Gameboy::new(vec![0; 0x8000]).unwrap());
+        debugger.invoke_command("break-add 105 if a == 1");
+        debugger.invoke_command("break-add 108 if a == 0");
+
+        debugger.invoke_command("continue");
+        assert_eq!(debugger.gameboy.pc(), 0x0108);
+    }
+
+    #[test]
+    fn breakpoints_only_count_instructions_that_start_there() {
+        // LD B,$12, NOP, JP $0100, forever.
+        let mut rom = vec![0; INITIAL_PC as usize];
+        rom.extend([0x06, 0x12, 0x00, 0xC3, 0x00, 0x01]);
+        let mut debugger = Debugger::new(Gameboy::new(rom).unwrap());
+        // PC passes over the operands of LD B,n and JP while executing them.
+        debugger.invoke_command("break-add 101");
+        debugger.invoke_command("break-add 104");
+        debugger.invoke_command("break-add 102 after 1");
+
+        debugger.invoke_command("continue");
+        assert_eq!(debugger.gameboy.pc(), 0x0102);
+        assert_eq!(debugger.gameboy.cpu().b, 0x12);
+        assert_eq!(debugger.breakpoints[&0x0101].hits, 0);
+        assert_eq!(debugger.breakpoints[&0x0104].hits, 0);
+        assert_eq!(debugger.breakpoints[&0x0102].hits, 2);
+    }
+
+    #[test]
+    fn parse_stepping_commands() {
+        assert_eq!(Command::parse("step").unwrap(), Command::Step);
+        assert_eq!(Command::parse("step-cycle").unwrap(), Command::StepCycle);
+        assert_eq!(Command::parse("over").unwrap(), Command::Over);
+        assert_eq!(Command::parse("out").unwrap(), Command::Out);
+        assert_eq!(Command::parse("until 0x150").unwrap(), Command::Until(0x0150));
+        assert!(matches!(Command::parse("until"), Err(CommandParseError::InvalidFormat)));
+        assert!(matches!(Command::parse("until here"), Err(CommandParseError::InvalidAddress)));
+    }
+
+    #[test]
+    fn step_runs_whole_instructions() {
+        // LD B,$12, then NOPs.
+        let mut rom = vec![0; 0x8000];
+        rom[INITIAL_PC as usize..][..2].copy_from_slice(&[0x06, 0x12]);
+        let mut debugger = Debugger::new(Gameboy::new(rom).unwrap());
+
+        debugger.invoke_command("step-cycle");
+        assert_eq!((debugger.gameboy.pc(), debugger.gameboy.cpu().b), (INITIAL_PC + 1, 0));
+        debugger.invoke_command("step");
+        assert_eq!((debugger.gameboy.pc(), debugger.gameboy.cpu().b), (INITIAL_PC + 2, 0x12));
+        debugger.invoke_command("over");
+        assert_eq!(debugger.gameboy.pc(), INITIAL_PC + 3);
+    }
+
+    #[test]
+    fn until_stops_at_breakpoints() {
+        // NOPs.
+        let mut debugger = Debugger::new(Gameboy::new(vec![0; 0x8000]).unwrap());
+        debugger.invoke_command("break-add 104");
+
+        debugger.invoke_command("until 108");
+        assert_eq!(debugger.gameboy.pc(), 0x0104);
+        debugger.invoke_command("until 108");
+        assert_eq!(debugger.gameboy.pc(), 0x0108);
+    }
+
+    #[test]
+    fn backtrace_shows_calls_with_symbols() {
+        let mut debugger = Debugger::new(Gameboy::new(Vec::new()).unwrap());
+        debugger.set_symbols(Symbols::parse("00:0150 Main\n00:0200 Delay\n"));
+        // CALL $0200.
+        debugger.call_stack.instruction_done(0x0150, 0xCD, 0x0200, 0xFFFC);
+        debugger.gameboy.cpu_mut().pc = 0x0203;
+
+        assert_eq!(debugger.backtrace(), [
+            "#0 0x0203 (Delay+0x3)",
+            "#1 0x0150 (Main) called 0x0200 (Delay)"
+        ]);
+
+        // RET to somewhere else.
+        debugger.call_stack.instruction_done(0x0203, 0xC9, 0x0300, 0xFFFE);
+        debugger.gameboy.cpu_mut().pc = 0x0300;
+        assert_eq!(debugger.backtrace(), [
+            "#0 0x0300 (Delay+0x100)",
+            "warning: the call stack may be wrong, since the return @ 0x203 went to 0x300, not \
+             0x153 where the call @ 0x150 would have returned to"
+        ]);
+    }
 }
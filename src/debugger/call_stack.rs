@@ -0,0 +1,219 @@
+//! A shadow of the call stack, kept up to date by looking at every instruction
+//! the CPU finishes, so "backtrace" can show how it got where it is.
+
+// Where the CPU jumps to when it dispatches each of the 5 interrupts.
+const INTERRUPT_VECTORS: [u16; 5] = [0x40, 0x48, 0x50, 0x58, 0x60];
+
+/// CALL, its conditional versions, and RST, which all push a return address.
+pub(super) fn is_call(opcode: u8) -> bool {
+    matches!(opcode, 0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC) || is_rst(opcode)
+}
+
+/// RET, its conditional versions, and RETI.
+pub(super) fn is_return(opcode: u8) -> bool {
+    matches!(opcode, 0xC0 | 0xC8 | 0xC9 | 0xD0 | 0xD8 | 0xD9)
+}
+
+fn is_rst(opcode: u8) -> bool {
+    opcode & 0xC7 == 0xC7
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(super) enum FrameKind {
+    Call,
+    Interrupt,
+}
+
+/// A return address the CPU has pushed and not returned to yet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(super) struct Frame {
+    pub(super) kind: FrameKind,
+    /// The CALL or RST, or where the interrupt happened.
+    pub(super) from: u16,
+    /// Where the CPU went.
+    pub(super) to: u16,
+    pub(super) return_address: u16,
+    /// SP after the return address was pushed, i.e. where it is on the stack.
+    pub(super) sp: u16,
+}
+
+pub(super) struct CallStack {
+    frames: Vec<Frame>,
+    // PC and SP at the start of the current instruction.
+    pc: u16,
+    sp: u16,
+    // Why the frames can't be trusted, if the CPU did something with the stack
+    // that they don't account for. This is cleared once the outermost frame
+    // returns normally.
+    desync: Option<String>,
+}
+
+impl CallStack {
+    /// An empty call stack for a CPU that is about to run the instruction at
+    /// `pc`.
+    pub(super) fn new(pc: u16, sp: u16) -> Self {
+        Self { frames: Vec::new(), pc, sp, desync: None }
+    }
+
+    /// The frames, from the outermost call to the innermost.
+    pub(super) fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub(super) fn desync(&self) -> Option<&str> {
+        self.desync.as_deref()
+    }
+
+    /// Updates the frames after the CPU has finished the instruction at
+    /// `address`, leaving PC and SP at `pc` and `sp`.
+    ///
+    /// Calls and returns are recognised by their opcode and by moving SP by
+    /// exactly 2, so conditional ones that aren't taken are ignored.
+    pub(super) fn instruction_done(&mut self, address: u16, opcode: u8, pc: u16, sp: u16) {
+        let pc_before = std::mem::replace(&mut self.pc, pc);
+        let before = std::mem::replace(&mut self.sp, sp);
+
+        if is_call(opcode) && sp == before.wrapping_sub(2) {
+            let len = if is_rst(opcode) { 1 } else { 3 };
+            let return_address = address.wrapping_add(len);
+            self.frames.push(Frame {
+                kind: FrameKind::Call,
+                from: address,
+                to: pc,
+                return_address,
+                sp,
+            });
+        } else if is_return(opcode) && sp == before.wrapping_add(2) {
+            match self.frames.pop() {
+                Some(frame) if frame.return_address == pc && frame.sp == before => {
+                    if self.frames.is_empty() {
+                        self.desync = None;
+                    }
+                },
+                Some(frame) => {
+                    self.desync = Some(format!(
+                    "the return @ {address:#X} went to {pc:#X}, not {:#X} where the call @ {:#X} \
+                     would have returned to",
+                    frame.return_address, frame.from
+                ))
+                },
+                None => {
+                    self.desync =
+                        Some(format!("the return @ {address:#X} went to {pc:#X} without a call"))
+                },
+            }
+        } else if sp == before.wrapping_sub(2) && INTERRUPT_VECTORS.contains(&pc) {
+            // The CPU pushes the address of the instruction it would have run next.
+            //
+            // TODO: Interrupts aren't dispatched yet, so this can't happen until
+            // they are.
+            self.frames.push(Frame {
+                kind: FrameKind::Interrupt,
+                from: pc_before,
+                to: pc,
+                return_address: pc_before,
+                sp,
+            });
+        }
+
+        // A return address above SP has been removed from the stack without
+        // returning to it, e.g. by POP or by changing SP.
+        while let Some(frame) = self.frames.last().filter(|frame| frame.sp < sp) {
+            self.desync = Some(format!(
+                "the return address pushed @ {:#X} was removed from the stack @ {address:#X}",
+                frame.from
+            ));
+            self.frames.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const NOP: u8 = 0x00;
+    const CALL: u8 = 0xCD;
+    const RST_38: u8 = 0xFF;
+    const RET: u8 = 0xC9;
+    const RET_NZ: u8 = 0xC0;
+    const RETI: u8 = 0xD9;
+    const PUSH_BC: u8 = 0xC5;
+    const POP_HL: u8 = 0xE1;
+
+    #[test]
+    fn calls_and_returns_push_and_pop_frames() {
+        let mut stack = CallStack::new(0x0150, 0xFFFE);
+        stack.instruction_done(0x0150, CALL, 0x0200, 0xFFFC);
+        stack.instruction_done(0x0200, RST_38, 0x0038, 0xFFFA);
+        assert_eq!(stack.frames(), [
+            Frame {
+                kind: FrameKind::Call,
+                from: 0x0150,
+                to: 0x0200,
+                return_address: 0x0153,
+                sp: 0xFFFC
+            },
+            Frame {
+                kind: FrameKind::Call,
+                from: 0x0200,
+                to: 0x0038,
+                return_address: 0x0201,
+                sp: 0xFFFA
+            },
+        ]);
+
+        // A conditional return that isn't taken doesn't pop anything.
+        stack.instruction_done(0x0038, RET_NZ, 0x0039, 0xFFFA);
+        stack.instruction_done(0x0039, RET, 0x0201, 0xFFFC);
+        stack.instruction_done(0x0201, RET, 0x0153, 0xFFFE);
+        assert!(stack.frames().is_empty());
+        assert_eq!(stack.desync(), None);
+    }
+
+    #[test]
+    fn interrupts_push_frames() {
+        let mut stack = CallStack::new(0x0150, 0xFFFE);
+        stack.instruction_done(0x0150, NOP, 0x0050, 0xFFFC);
+        assert_eq!(stack.frames(), [Frame {
+            kind: FrameKind::Interrupt,
+            from: 0x0150,
+            to: 0x0050,
+            return_address: 0x0150,
+            sp: 0xFFFC
+        }]);
+        stack.instruction_done(0x0050, RETI, 0x0150, 0xFFFE);
+        assert!(stack.frames().is_empty());
+    }
+
+    #[test]
+    fn stack_manipulation_is_flagged() {
+        // A return to somewhere other than after the call, e.g. after PUSH.
+        let mut stack = CallStack::new(0x0150, 0xFFFE);
+        stack.instruction_done(0x0150, CALL, 0x0200, 0xFFFC);
+        stack.instruction_done(0x0200, PUSH_BC, 0x0201, 0xFFFA);
+        stack.instruction_done(0x0201, RET, 0x1234, 0xFFFC);
+        assert_eq!(
+            stack.desync(),
+            Some(
+                "the return @ 0x201 went to 0x1234, not 0x153 where the call @ 0x150 would have \
+                 returned to"
+            )
+        );
+
+        // A return address that is popped instead of returned to.
+        let mut stack = CallStack::new(0x0150, 0xFFFE);
+        stack.instruction_done(0x0150, CALL, 0x0200, 0xFFFC);
+        stack.instruction_done(0x0200, POP_HL, 0x0201, 0xFFFE);
+        assert!(stack.frames().is_empty());
+        assert_eq!(
+            stack.desync(),
+            Some("the return address pushed @ 0x150 was removed from the stack @ 0x200")
+        );
+
+        // Returning without a call.
+        let mut stack = CallStack::new(0x0150, 0xFFFC);
+        stack.instruction_done(0x0150, RET, 0x4000, 0xFFFE);
+        assert_eq!(stack.desync(), Some("the return @ 0x150 went to 0x4000 without a call"));
+    }
+}
@@ -0,0 +1,288 @@
+//! The conditions of conditional breakpoints, like `a == $10 && [HL] != 0`.
+//!
+//! A condition is an expression made of:
+//! * numbers, in decimal or in hex with `$` or `0x` in front (register names
+//!   like `a` and `bc` would be hex numbers otherwise),
+//! * registers (`a`-`l`, `af`, `bc`, `de`, `hl`, `pc` and `sp`) and flags
+//!   (`zf`, `nf`, `hf` and `cf`),
+//! * the byte at an address in memory, e.g. `[hl]` or `[$C000]`,
+//! * comparisons (`==`, `!=`, `<`, `<=`, `>` and `>=`),
+//! * and boolean operators (`!`, `&&` and `||`), with parentheses for grouping.
+//!
+//! Everything is a 16-bit number, where comparisons and boolean operators give
+//! 1 for true and 0 for false. A condition is met if it isn't 0.
+
+use std::fmt;
+
+use super::SetTarget;
+use crate::gb::Gameboy;
+
+// The symbols that can appear in a condition, with the longer ones first so
+// "<=" isn't read as "<" then "=".
+const SYMBOLS: [&str; 13] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "[", "]", "(", ")"];
+
+/// A parsed condition, which keeps the text it was parsed from to show it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct Condition {
+    source: String,
+    expression: Expression,
+}
+
+impl Condition {
+    pub(super) fn parse(source: &str) -> Result<Self, ConditionError> {
+        let mut parser = Parser { tokens: tokenize(source)?, position: 0 };
+        let expression = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(ConditionError::Unexpected(token.to_owned()));
+        }
+        Ok(Self { source: source.trim().to_owned(), expression })
+    }
+
+    /// Whether the condition is met. Memory is read without any side effects.
+    pub(super) fn is_met(&self, gameboy: &Gameboy) -> bool {
+        self.expression.evaluate(gameboy) != 0
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(super) enum ConditionError {
+    #[error("invalid number: {0}")]
+    InvalidNumber(String),
+    #[error("unknown register or flag: {0}")]
+    UnknownName(String),
+    #[error("unexpected {0}")]
+    Unexpected(String),
+    #[error("unexpected end of condition")]
+    UnexpectedEnd,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Expression {
+    Number(u16),
+    Register(SetTarget),
+    Memory(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(Box<Expression>, Operator, Box<Expression>),
+}
+
+impl Expression {
+    fn evaluate(&self, gameboy: &Gameboy) -> u16 {
+        match self {
+            Self::Number(value) => *value,
+            Self::Register(target) => target.read(gameboy.cpu()),
+            Self::Memory(address) => gameboy.read_memory(address.evaluate(gameboy)) as u16,
+            Self::Not(operand) => (operand.evaluate(gameboy) == 0) as u16,
+            Self::Binary(left, operator, right) => {
+                let left = left.evaluate(gameboy);
+                // && and || only evaluate the right side if they need to, like
+                // they do in Rust.
+                let result = match operator {
+                    Operator::And => left != 0 && right.evaluate(gameboy) != 0,
+                    Operator::Or => left != 0 || right.evaluate(gameboy) != 0,
+                    Operator::Equal => left == right.evaluate(gameboy),
+                    Operator::NotEqual => left != right.evaluate(gameboy),
+                    Operator::Less => left < right.evaluate(gameboy),
+                    Operator::LessOrEqual => left <= right.evaluate(gameboy),
+                    Operator::Greater => left > right.evaluate(gameboy),
+                    Operator::GreaterOrEqual => left >= right.evaluate(gameboy),
+                };
+                result as u16
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Operator {
+    And,
+    Or,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Operator {
+    fn comparison(symbol: &str) -> Option<Self> {
+        match symbol {
+            "==" => Some(Self::Equal),
+            "!=" => Some(Self::NotEqual),
+            "<" => Some(Self::Less),
+            "<=" => Some(Self::LessOrEqual),
+            ">" => Some(Self::Greater),
+            ">=" => Some(Self::GreaterOrEqual),
+            _ => None,
+        }
+    }
+}
+
+// Splits a condition into symbols, and words made of letters, digits and `$`,
+// which are either numbers or names.
+fn tokenize(source: &str) -> Result<Vec<&str>, ConditionError> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while let Some(next) = rest.chars().next() {
+        let len = if next.is_ascii_alphanumeric() || next == '$' {
+            rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '$').unwrap_or(rest.len())
+        } else {
+            match SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+                Some(symbol) => symbol.len(),
+                None => return Err(ConditionError::Unexpected(next.to_string())),
+            }
+        };
+        tokens.push(&rest[..len]);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+// A recursive descent parser, with a method for each level of precedence from
+// lowest to highest.
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Result<&'a str, ConditionError> {
+        let token = self.peek().ok_or(ConditionError::UnexpectedEnd)?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    // Skips the next token if it is `symbol`, and returns whether it was.
+    fn eat(&mut self, symbol: &str) -> bool {
+        let found = self.peek() == Some(symbol);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), ConditionError> {
+        match self.next()? {
+            token if token == symbol => Ok(()),
+            token => Err(ConditionError::Unexpected(token.to_owned())),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expression, ConditionError> {
+        let mut left = self.and()?;
+        while self.eat("||") {
+            left = Expression::Binary(Box::new(left), Operator::Or, Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expression, ConditionError> {
+        let mut left = self.comparison()?;
+        while self.eat("&&") {
+            left = Expression::Binary(Box::new(left), Operator::And, Box::new(self.comparison()?));
+        }
+        Ok(left)
+    }
+
+    // Comparisons can't be chained, since `a < b < c` wouldn't mean what it
+    // looks like.
+    fn comparison(&mut self) -> Result<Expression, ConditionError> {
+        let left = self.unary()?;
+        match self.peek().and_then(Operator::comparison) {
+            Some(operator) => {
+                self.position += 1;
+                Ok(Expression::Binary(Box::new(left), operator, Box::new(self.unary()?)))
+            },
+            None => Ok(left),
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expression, ConditionError> {
+        if self.eat("!") {
+            return Ok(Expression::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expression, ConditionError> {
+        match self.next()? {
+            "[" => {
+                let address = self.or()?;
+                self.expect("]")?;
+                Ok(Expression::Memory(Box::new(address)))
+            },
+            "(" => {
+                let expression = self.or()?;
+                self.expect(")")?;
+                Ok(expression)
+            },
+            token if token.starts_with(|c: char| c.is_ascii_digit() || c == '$') => {
+                let number = match token.strip_prefix('$').or_else(|| token.strip_prefix("0x")) {
+                    Some(hex) => u16::from_str_radix(hex, 16),
+                    None => token.parse(),
+                };
+                number
+                    .map(Expression::Number)
+                    .map_err(|_| ConditionError::InvalidNumber(token.to_owned()))
+            },
+            token if token.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                SetTarget::parse(token)
+                    .map(Expression::Register)
+                    .ok_or_else(|| ConditionError::UnknownName(token.to_owned()))
+            },
+            token => Err(ConditionError::Unexpected(token.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gb::Register16;
+
+    #[test]
+    fn evaluate_conditions() {
+        let mut gameboy = Gameboy::new(Vec::new()).unwrap();
+        let cpu = gameboy.cpu_mut();
+        cpu.a = 0x10;
+        cpu.f = 0x80;
+        cpu.set_register16(Register16::HL, 0x8000);
+        gameboy.poke_memory(0x8000, 0xFF);
+        gameboy.poke_memory(0x8001, 0x01);
+
+        let is_met = |source: &str| Condition::parse(source).unwrap().is_met(&gameboy);
+        assert!(is_met("a == $10"));
+        assert!(is_met("a == 16 && zf"));
+        assert!(!is_met("a != 0x10 || !zf"));
+        assert!(is_met("[HL] == $FF"));
+        assert!(is_met("[$8001] < [hl]"));
+        assert!(is_met("[hl] >= 255 && (cf || a <= 16)"));
+        assert!(!is_met("0"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(Condition::parse("a =="), Err(ConditionError::UnexpectedEnd)));
+        assert!(
+            matches!(Condition::parse("x == 1"), Err(ConditionError::UnknownName(name)) if name == "x")
+        );
+        assert!(matches!(Condition::parse("a == $10000"), Err(ConditionError::InvalidNumber(_))));
+        assert!(matches!(Condition::parse("[hl == 1"), Err(ConditionError::UnexpectedEnd)));
+        assert!(
+            matches!(Condition::parse("a == 1 b"), Err(ConditionError::Unexpected(token)) if token == "b")
+        );
+        assert!(
+            matches!(Condition::parse("a = 1"), Err(ConditionError::Unexpected(token)) if token == "=")
+        );
+    }
+}
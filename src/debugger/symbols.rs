@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+// Where ROM bank 1 and up are mapped, and where ROM ends.
+const SWITCHABLE_BANK_START: u16 = 0x4000;
+const ROM_END: u16 = 0x7FFF;
+
+/// Names for addresses, from a symbol file in the format written by RGBDS
+/// (`rgblink -n`) and read by most Gameboy debuggers, which has a line like
+/// `01:4000 Name` for every label, with the bank and address in hex.
+///
+/// The debugger doesn't keep track of which ROM bank is mapped, so only the
+/// labels in banks 0 and 1 are used.
+#[derive(Debug, Default)]
+pub struct Symbols {
+    labels: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    // Lines that aren't labels, like comments (starting with `;`) and the
+    // section headers of newer formats, are skipped.
+    pub(super) fn parse(text: &str) -> Self {
+        let mut labels = BTreeMap::new();
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or_default();
+            let Some((location, name)) = line.trim().split_once(char::is_whitespace) else {
+                continue;
+            };
+            let Some((bank, address)) = location.split_once(':') else {
+                continue;
+            };
+            let (Ok(bank), Ok(address)) =
+                (u16::from_str_radix(bank, 16), u16::from_str_radix(address, 16))
+            else {
+                continue;
+            };
+            let mapped = if address < SWITCHABLE_BANK_START {
+                bank == 0
+            } else if address <= ROM_END {
+                bank <= 1
+            } else {
+                true
+            };
+            if mapped {
+                labels.entry(address).or_insert_with(|| name.trim().to_owned());
+            }
+        }
+        Self { labels }
+    }
+
+    /// The label at or closest before `address`, as `Name` or `Name+0x12`. A
+    /// label in ROM only names addresses in ROM, and one outside of it only
+    /// names addresses outside of it.
+    pub fn name(&self, address: u16) -> Option<String> {
+        let (label_address, label) = self.labels.range(..=address).next_back()?;
+        if (*label_address <= ROM_END) != (address <= ROM_END) {
+            return None;
+        }
+        match address - label_address {
+            0 => Some(label.clone()),
+            offset => Some(format!("{label}+{offset:#X}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names_addresses_after_the_closest_label() {
+        let symbols = Symbols::parse(
+            "; File generated by rgblink
+[labels]
+00:0150 Main
+00:0200 Delay
+01:4000 Bank1
+02:4000 Bank2
+00:C000 wCounter
+",
+        );
+
+        assert_eq!(symbols.name(0x0150).as_deref(), Some("Main"));
+        assert_eq!(symbols.name(0x0153).as_deref(), Some("Main+0x3"));
+        assert_eq!(symbols.name(0x0210).as_deref(), Some("Delay+0x10"));
+        assert_eq!(symbols.name(0x4001).as_deref(), Some("Bank1+0x1"));
+        assert_eq!(symbols.name(0xC002).as_deref(), Some("wCounter+0x2"));
+        assert_eq!(symbols.name(0x0100), None);
+        assert_eq!(symbols.name(0x8000), None);
+    }
+}
@@ -0,0 +1,347 @@
+//! Turns machine code back into assembly, e.g. `C3 50 01` into `JP $0150`.
+//!
+//! Every opcode can be disassembled, including the ones boyo can't execute
+//! yet. Illegal opcodes are shown as data bytes (`DB $D3`).
+
+use std::fmt;
+
+const REGISTERS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const ALU_OPERATIONS: [&str; 8] =
+    ["ADD A,", "ADC A,", "SUB ", "SBC A,", "AND ", "XOR ", "OR ", "CP "];
+const CB_OPERATIONS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+
+/// A single disassembled instruction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Disassembly {
+    pub address: u16,
+    /// The opcode followed by its operands, if any.
+    pub bytes: Vec<u8>,
+    /// The instruction with its operands, e.g. `LD A,(HL+)` or `JP $0150`.
+    pub mnemonic: String,
+}
+
+impl Disassembly {
+    /// The address of the instruction after this one.
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.bytes.len() as u16)
+    }
+}
+
+impl fmt::Display for Disassembly {
+    /// Formats the address, the raw bytes and the mnemonic, e.g.
+    /// `0150  C3 50 01  JP $0150`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<_> = self.bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+        write!(f, "{:04X}  {:<8}  {}", self.address, bytes.join(" "), self.mnemonic)
+    }
+}
+
+/// Disassembles the instruction at `address`, reading its bytes with `read`.
+pub fn disassemble(address: u16, read: impl Fn(u16) -> u8) -> Disassembly {
+    let opcode = read(address);
+    if opcode == 0xCB {
+        let operand = read(address.wrapping_add(1));
+        return Disassembly {
+            address,
+            bytes: vec![opcode, operand],
+            mnemonic: cb_mnemonic(operand),
+        };
+    }
+    // STOP is followed by a byte that the CPU skips, which is normally 0.
+    if opcode == 0x10 {
+        let ignored = read(address.wrapping_add(1));
+        return Disassembly { address, bytes: vec![opcode, ignored], mnemonic: "STOP".to_owned() };
+    }
+
+    let template = template(opcode);
+    let mut bytes = vec![opcode];
+    let Some((prefix, rest)) = template.split_once('{') else {
+        return Disassembly { address, bytes, mnemonic: template };
+    };
+    let (operand, suffix) = rest.split_once('}').expect("unterminated operand in template");
+
+    let mut next_byte = || {
+        let byte = read(address.wrapping_add(bytes.len() as u16));
+        bytes.push(byte);
+        byte
+    };
+    let operand = match operand {
+        "n8" => format!("${:02X}", next_byte()),
+        "n16" => {
+            let low = next_byte() as u16;
+            format!("${:04X}", (next_byte() as u16) << 8 | low)
+        },
+        // The upper page of memory, where the I/O registers are.
+        "a8" => format!("$FF{:02X}", next_byte()),
+        // A jump relative to the next instruction, shown as the target.
+        "r8" => {
+            let offset = next_byte() as i8;
+            format!("${:04X}", address.wrapping_add(2).wrapping_add(offset as u16))
+        },
+        "e8" => format!("{:+}", next_byte() as i8),
+        _ => unreachable!("unknown operand {}", operand),
+    };
+
+    Disassembly { address, bytes, mnemonic: format!("{prefix}{operand}{suffix}") }
+}
+
+/// Disassembles every instruction that starts between `start` and `end`
+/// (inclusive).
+pub fn disassemble_range(start: u16, end: u16, read: impl Fn(u16) -> u8) -> Vec<Disassembly> {
+    let mut instructions = Vec::new();
+    let mut address = start as u32;
+    while address <= end as u32 {
+        let instruction = disassemble(address as u16, &read);
+        address += instruction.bytes.len() as u32;
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+// The mnemonic of an opcode without a prefix, with its operand (if it has one)
+// as a placeholder in braces.
+fn template(opcode: u8) -> String {
+    let register = |index: u8| REGISTERS[index as usize & 0x07];
+    match opcode {
+        0x76 => "HALT".to_owned(),
+        0x40..=0x7F => format!("LD {},{}", register(opcode >> 3), register(opcode)),
+        0x80..=0xBF => {
+            format!("{}{}", ALU_OPERATIONS[(opcode as usize >> 3) & 0x07], register(opcode))
+        },
+        _ => template_irregular(opcode).to_owned(),
+    }
+}
+
+fn template_irregular(opcode: u8) -> &'static str {
+    match opcode {
+        0x00 => "NOP",
+        0x01 => "LD BC,{n16}",
+        0x02 => "LD (BC),A",
+        0x03 => "INC BC",
+        0x04 => "INC B",
+        0x05 => "DEC B",
+        0x06 => "LD B,{n8}",
+        0x07 => "RLCA",
+        0x08 => "LD ({n16}),SP",
+        0x09 => "ADD HL,BC",
+        0x0A => "LD A,(BC)",
+        0x0B => "DEC BC",
+        0x0C => "INC C",
+        0x0D => "DEC C",
+        0x0E => "LD C,{n8}",
+        0x0F => "RRCA",
+
+        0x10 => "STOP",
+        0x11 => "LD DE,{n16}",
+        0x12 => "LD (DE),A",
+        0x13 => "INC DE",
+        0x14 => "INC D",
+        0x15 => "DEC D",
+        0x16 => "LD D,{n8}",
+        0x17 => "RLA",
+        0x18 => "JR {r8}",
+        0x19 => "ADD HL,DE",
+        0x1A => "LD A,(DE)",
+        0x1B => "DEC DE",
+        0x1C => "INC E",
+        0x1D => "DEC E",
+        0x1E => "LD E,{n8}",
+        0x1F => "RRA",
+
+        0x20 => "JR NZ,{r8}",
+        0x21 => "LD HL,{n16}",
+        0x22 => "LD (HL+),A",
+        0x23 => "INC HL",
+        0x24 => "INC H",
+        0x25 => "DEC H",
+        0x26 => "LD H,{n8}",
+        0x27 => "DAA",
+        0x28 => "JR Z,{r8}",
+        0x29 => "ADD HL,HL",
+        0x2A => "LD A,(HL+)",
+        0x2B => "DEC HL",
+        0x2C => "INC L",
+        0x2D => "DEC L",
+        0x2E => "LD L,{n8}",
+        0x2F => "CPL",
+
+        0x30 => "JR NC,{r8}",
+        0x31 => "LD SP,{n16}",
+        0x32 => "LD (HL-),A",
+        0x33 => "INC SP",
+        0x34 => "INC (HL)",
+        0x35 => "DEC (HL)",
+        0x36 => "LD (HL),{n8}",
+        0x37 => "SCF",
+        0x38 => "JR C,{r8}",
+        0x39 => "ADD HL,SP",
+        0x3A => "LD A,(HL-)",
+        0x3B => "DEC SP",
+        0x3C => "INC A",
+        0x3D => "DEC A",
+        0x3E => "LD A,{n8}",
+        0x3F => "CCF",
+
+        0xC0 => "RET NZ",
+        0xC1 => "POP BC",
+        0xC2 => "JP NZ,{n16}",
+        0xC3 => "JP {n16}",
+        0xC4 => "CALL NZ,{n16}",
+        0xC5 => "PUSH BC",
+        0xC6 => "ADD A,{n8}",
+        0xC7 => "RST $00",
+        0xC8 => "RET Z",
+        0xC9 => "RET",
+        0xCA => "JP Z,{n16}",
+        0xCC => "CALL Z,{n16}",
+        0xCD => "CALL {n16}",
+        0xCE => "ADC A,{n8}",
+        0xCF => "RST $08",
+
+        0xD0 => "RET NC",
+        0xD1 => "POP DE",
+        0xD2 => "JP NC,{n16}",
+        0xD4 => "CALL NC,{n16}",
+        0xD5 => "PUSH DE",
+        0xD6 => "SUB {n8}",
+        0xD7 => "RST $10",
+        0xD8 => "RET C",
+        0xD9 => "RETI",
+        0xDA => "JP C,{n16}",
+        0xDC => "CALL C,{n16}",
+        0xDE => "SBC A,{n8}",
+        0xDF => "RST $18",
+
+        0xE0 => "LDH ({a8}),A",
+        0xE1 => "POP HL",
+        0xE2 => "LD ($FF00+C),A",
+        0xE5 => "PUSH HL",
+        0xE6 => "AND {n8}",
+        0xE7 => "RST $20",
+        0xE8 => "ADD SP,{e8}",
+        0xE9 => "JP HL",
+        0xEA => "LD ({n16}),A",
+        0xEE => "XOR {n8}",
+        0xEF => "RST $28",
+
+        0xF0 => "LDH A,({a8})",
+        0xF1 => "POP AF",
+        0xF2 => "LD A,($FF00+C)",
+        0xF3 => "DI",
+        0xF5 => "PUSH AF",
+        0xF6 => "OR {n8}",
+        0xF7 => "RST $30",
+        0xF8 => "LD HL,SP{e8}",
+        0xF9 => "LD SP,HL",
+        0xFA => "LD A,({n16})",
+        0xFB => "EI",
+        0xFE => "CP {n8}",
+        0xFF => "RST $38",
+
+        0xD3 => "DB $D3",
+        0xDB => "DB $DB",
+        0xDD => "DB $DD",
+        0xE3 => "DB $E3",
+        0xE4 => "DB $E4",
+        0xEB => "DB $EB",
+        0xEC => "DB $EC",
+        0xED => "DB $ED",
+        0xF4 => "DB $F4",
+        0xFC => "DB $FC",
+        0xFD => "DB $FD",
+
+        _ => unreachable!("opcode 0x{:02X} isn't irregular", opcode),
+    }
+}
+
+// The mnemonic of the opcode after a 0xCB prefix.
+fn cb_mnemonic(opcode: u8) -> String {
+    let register = REGISTERS[opcode as usize & 0x07];
+    let bit = (opcode >> 3) & 0x07;
+    match opcode >> 6 {
+        0 => format!("{} {}", CB_OPERATIONS[bit as usize], register),
+        1 => format!("BIT {bit},{register}"),
+        2 => format!("RES {bit},{register}"),
+        _ => format!("SET {bit},{register}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn disassemble_bytes(bytes: &[u8]) -> Disassembly {
+        disassemble(0x0150, |address| bytes.get(address as usize - 0x0150).copied().unwrap_or(0))
+    }
+
+    #[test]
+    fn disassemble_operands() {
+        assert_eq!(disassemble_bytes(&[0x00]).mnemonic, "NOP");
+        assert_eq!(disassemble_bytes(&[0x2A]).mnemonic, "LD A,(HL+)");
+        assert_eq!(disassemble_bytes(&[0x3E, 0x2A]).mnemonic, "LD A,$2A");
+        assert_eq!(disassemble_bytes(&[0xC3, 0x50, 0x01]).mnemonic, "JP $0150");
+        assert_eq!(disassemble_bytes(&[0xE0, 0x44]).mnemonic, "LDH ($FF44),A");
+        assert_eq!(disassemble_bytes(&[0xF8, 0xFE]).mnemonic, "LD HL,SP-2");
+        assert_eq!(disassemble_bytes(&[0xE8, 0x05]).mnemonic, "ADD SP,+5");
+        assert_eq!(disassemble_bytes(&[0xD3]).mnemonic, "DB $D3");
+    }
+
+    #[test]
+    fn relative_jumps_show_their_target() {
+        // JR -2 jumps back to itself.
+        assert_eq!(disassemble_bytes(&[0x18, 0xFE]).mnemonic, "JR $0150");
+        assert_eq!(disassemble_bytes(&[0x20, 0x10]).mnemonic, "JR NZ,$0162");
+    }
+
+    #[test]
+    fn disassemble_regular_blocks() {
+        assert_eq!(disassemble_bytes(&[0x41]).mnemonic, "LD B,C");
+        assert_eq!(disassemble_bytes(&[0x76]).mnemonic, "HALT");
+        assert_eq!(disassemble_bytes(&[0x7E]).mnemonic, "LD A,(HL)");
+        assert_eq!(disassemble_bytes(&[0x86]).mnemonic, "ADD A,(HL)");
+        assert_eq!(disassemble_bytes(&[0x90]).mnemonic, "SUB B");
+        assert_eq!(disassemble_bytes(&[0xBF]).mnemonic, "CP A");
+    }
+
+    #[test]
+    fn disassemble_cb_prefix() {
+        assert_eq!(disassemble_bytes(&[0xCB, 0x37]).mnemonic, "SWAP A");
+        assert_eq!(disassemble_bytes(&[0xCB, 0x7C]).mnemonic, "BIT 7,H");
+        assert_eq!(disassemble_bytes(&[0xCB, 0x86]).mnemonic, "RES 0,(HL)");
+        assert_eq!(disassemble_bytes(&[0xCB, 0xFF]).mnemonic, "SET 7,A");
+        assert_eq!(disassemble_bytes(&[0xCB, 0x37]).bytes, [0xCB, 0x37]);
+    }
+
+    #[test]
+    fn stop_is_two_bytes() {
+        let instructions = disassemble_range(0x0150, 0x0152, |address| match address {
+            0x0150 => 0x10,
+            0x0151 => 0x00,
+            0x0152 => 0x3E,
+            _ => 0x2A,
+        });
+        let lines: Vec<_> = instructions.iter().map(ToString::to_string).collect();
+        assert_eq!(lines, ["0150  10 00     STOP", "0152  3E 2A     LD A,$2A"]);
+    }
+
+    #[test]
+    fn every_opcode_can_be_disassembled() {
+        for opcode in 0..=0xFF {
+            let disassembly = disassemble(0, |address| if address == 0 { opcode } else { 0 });
+            assert!(!disassembly.mnemonic.contains('{'), "{}", disassembly.mnemonic);
+        }
+    }
+
+    #[test]
+    fn display_includes_address_and_bytes() {
+        let instructions = disassemble_range(0x0150, 0x0153, |address| match address {
+            0x0150 => 0x3E,
+            0x0151 => 0x2A,
+            0x0152 => 0xC3,
+            0x0153 => 0x50,
+            _ => 0x01,
+        });
+        let lines: Vec<_> = instructions.iter().map(ToString::to_string).collect();
+        assert_eq!(lines, ["0150  3E 2A     LD A,$2A", "0152  C3 50 01  JP $0150"]);
+    }
+}
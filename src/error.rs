@@ -0,0 +1,20 @@
+use std::io;
+
+/// Why emulation couldn't continue.
+///
+/// The stepping methods of [`crate::Gameboy`] return this before changing any
+/// state, so the Gameboy can still be inspected afterwards.
+#[derive(Debug, thiserror::Error)]
+pub enum EmulationError {
+    /// An instruction that boyo doesn't implement yet. Illegal opcodes aren't
+    /// an error, they lock up the CPU like on hardware (see
+    /// [`crate::Gameboy::lockup`]).
+    #[error("not impl: 0x{opcode:02X} @ {pc:#06X}")]
+    UnimplementedOpcode { opcode: u8, pc: u16 },
+    #[error("ROM read out of range @ {address:#06X}, the ROM is only {len:#X} bytes")]
+    RomOutOfRange { address: u16, len: usize },
+    #[error("unsupported cartridge type 0x{0:02X}")]
+    UnsupportedCartridge(u8),
+    #[error("failed to read ROM: {0}")]
+    Io(#[from] io::Error),
+}
@@ -1,61 +1,222 @@
 use std::fmt::{self, UpperHex};
-use std::thread::sleep;
-use std::time::{Duration, Instant};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
 
 use log::log_enabled;
 
+use crate::apu::{Apu, StereoSample};
+use crate::bus::{AccessEvent, Bus, BusAccess};
+use crate::cartridge::{self, Cartridge};
+use crate::error::EmulationError;
 use crate::instruction::Instruction;
+use crate::joypad::{self, Button, Joypad};
+use crate::pacing::{FramePacer, Speed};
+use crate::ppu::{self, Framebuffer, Ppu};
+use crate::serial::{self, Serial, SerialLink};
+use crate::trace::TraceLine;
+use crate::wav::WavRecorder;
 
 pub const INITIAL_PC: u16 = 0x0100;
 
 const INITIAL_SP: u16 = 0xFFFE;
 
-pub struct Gameboy {
-    system: System,
+/// The speed of the main clock, in T-cycles per second. An M-cycle is 4
+/// T-cycles.
+pub const CLOCK_RATE: u32 = 4_194_304;
+
+/// M-cycles per frame: 154 scanlines (including VBlank) of 114 M-cycles each.
+pub const CYCLES_PER_FRAME: u32 = 17_556;
+
+const WRAM_START: u16 = 0xC000;
+const WRAM_END: u16 = 0xDFFF;
+// Echo RAM mirrors the first 7.5 KiB of WRAM.
+const ECHO_START: u16 = 0xE000;
+const ECHO_END: u16 = 0xFDFF;
+const IO_START: u16 = 0xFF00;
+const IO_END: u16 = 0xFF7F;
+const HRAM_START: u16 = 0xFF80;
+const HRAM_END: u16 = 0xFFFE;
+const DIV: u16 = 0xFF04;
+const IF: u16 = 0xFF0F;
+const DMA: u16 = 0xFF46;
+const IE: u16 = 0xFFFF;
+
+const INTERRUPT_SERIAL: u8 = 1 << 3;
+
+// The APU frame sequencer is clocked when bit 4 of DIV (bit 12 of the internal
+// system counter) goes from 1 to 0, which happens at 512 Hz.
+const FRAME_SEQUENCER_COUNTER_BIT: u16 = 1 << 12;
+
+// The serial port shifts a bit when bit 8 of the system counter goes from 1 to
+// 0, which happens at 8192 Hz.
+const SERIAL_COUNTER_BIT: u16 = 1 << 8;
+
+/// A Gameboy CPU connected to a [`Bus`], which is the Gameboy's own hardware
+/// unless it is made with [`Gameboy::from_parts`].
+pub struct Gameboy<B: Bus = System> {
+    cpu: CpuState,
+    bus: B,
     instruction_state: InstructionState,
+    lockup: Option<Lockup>,
+    software_breakpoint: Option<u16>,
+    trace: Option<Box<dyn Write>>,
 }
 
 impl Gameboy {
     #[cfg(test)]
     fn no_cartridge() -> Self {
-        Self::new(Vec::new())
+        Self::new(Vec::new()).unwrap()
     }
 
-    pub fn new(rom: Vec<u8>) -> Self {
-        Self { system: System::new(rom), instruction_state: InstructionState::default() }
+    /// Creates a Gameboy with the given ROM inserted.
+    ///
+    /// ROMs without a valid cartridge header, such as small test programs, are
+    /// treated as if they only had ROM.
+    pub fn new(rom: Vec<u8>) -> Result<Self, EmulationError> {
+        Ok(Self::from_parts(CpuState::default(), System::new(Cartridge::new(rom)?)))
     }
 
-    pub fn execute(mut self) -> ! {
-        const CYCLES_PER_FRAME: u32 = 69905;
-        const REFRESH_RATE: f64 = 60.0;
+    /// Creates a Gameboy with the ROM file at `path` inserted.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, EmulationError> {
+        Self::new(fs::read(path)?)
+    }
 
-        let frame_duration = Duration::from_secs_f64(1.0 / REFRESH_RATE);
+    /// Runs forever at the given speed, or until emulation fails.
+    pub fn execute(mut self, speed: Speed, mut audio_out: Option<WavRecorder>) -> EmulationError {
+        let mut pacer = FramePacer::new(speed);
 
         loop {
-            let start = Instant::now();
-            for _ in 0..CYCLES_PER_FRAME {
-                if log_enabled!(log::Level::Trace) {
-                    match self.peek_instruction_state() {
-                        Ok(state) => log::trace!("{state}"),
-                        Err(opcode) => log::trace!("0x{opcode:02X}"),
-                    }
+            if let Err(error) = self.run_frame() {
+                return error;
+            }
+            self.output_audio(&mut audio_out);
+            if pacer.next_frame() {
+                // TODO: Actually draw frame.
+                log::trace!("Draw frame");
+            }
+        }
+    }
+
+    /// Runs as fast as possible until `limit` is reached, without drawing
+    /// anything.
+    ///
+    /// Execution stops early if emulation fails, in which case the error is
+    /// returned.
+    pub fn execute_headless(
+        &mut self,
+        limit: RunLimit,
+        mut audio_out: Option<WavRecorder>,
+    ) -> Result<(), EmulationError> {
+        let mut result = Ok(());
+        for cycle in 1..=limit.cycles() {
+            self.trace();
+            if let Err(error) = self.cycle() {
+                result = Err(error);
+                break;
+            }
+
+            if cycle % CYCLES_PER_FRAME as u64 == 0 {
+                self.output_audio(&mut audio_out);
+            }
+        }
+
+        self.output_audio(&mut audio_out);
+        self.flush_trace();
+        result
+    }
+
+    /// Writes the audio produced since the last call to `audio_out`, or throws
+    /// it away if there is nowhere for it to go.
+    pub fn output_audio(&mut self, audio_out: &mut Option<WavRecorder>) {
+        match audio_out {
+            Some(recorder) => {
+                if let Err(error) = recorder.record(self.audio_samples()) {
+                    log::error!("Failed to write audio output, recording stopped: {error}");
+                    *audio_out = None;
                 }
-                self.cycle();
+            },
+            // TODO: Actually play audio.
+            None => self.audio_samples().for_each(drop),
+        }
+    }
+
+    /// The last frame drawn by the PPU.
+    pub fn framebuffer(&self) -> &Framebuffer {
+        self.bus.ppu.framebuffer()
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if self.bus.joypad.set_button(button, pressed) {
+            self.bus.interrupt_flag |= joypad::INTERRUPT_JOYPAD;
+        }
+    }
+
+    /// Plugs a device into the serial port, replacing whatever was connected
+    /// before.
+    pub fn set_serial_link(&mut self, link: Box<dyn SerialLink>) {
+        self.bus.serial.set_link(link);
+    }
+
+    /// Removes and returns the stereo samples the APU has produced since the
+    /// last call, at [`crate::apu::SAMPLE_RATE`].
+    pub fn audio_samples(&mut self) -> impl Iterator<Item = StereoSample> + '_ {
+        self.bus.apu.drain_samples()
+    }
+}
+
+impl<B: Bus> Gameboy<B> {
+    /// Creates a Gameboy whose CPU starts in the given state, connected to
+    /// `bus`.
+    pub fn from_parts(cpu: CpuState, bus: B) -> Self {
+        Self {
+            cpu,
+            bus,
+            instruction_state: InstructionState::default(),
+            lockup: None,
+            software_breakpoint: None,
+            trace: None,
+        }
+    }
+
+    /// Runs for one frame's worth of cycles.
+    pub fn run_frame(&mut self) -> Result<(), EmulationError> {
+        for _ in 0..CYCLES_PER_FRAME {
+            self.trace();
+            self.cycle()?;
+        }
+        Ok(())
+    }
+
+    fn trace(&self) {
+        if log_enabled!(log::Level::Trace) {
+            match self.peek_instruction_state() {
+                Ok(state) => log::trace!("{state}"),
+                Err(error) => log::trace!("{error}"),
             }
-            // TODO: Actually draw frame.
-            println!("Draw frame");
-            sleep(frame_duration.checked_sub(start.elapsed()).unwrap_or(Duration::ZERO));
         }
     }
 
-    pub fn cycle(&mut self) {
+    /// Runs for a single M-cycle.
+    pub fn cycle(&mut self) -> Result<(), EmulationError> {
+        // The CPU stops for good after a lockup, but the rest of the hardware keeps
+        // running.
+        if self.lockup.is_some() {
+            self.bus.tick();
+            return Ok(());
+        }
+
         if self.instruction_state.is_done() {
-            let byte = self.system.fetch();
-            let instruction = Instruction::from_opcode(byte).expect("invalid opcode");
-            self.instruction_state = InstructionState { instruction, m_cycle: 0 };
+            let address = self.cpu.pc;
+            let instruction = decode(self.bus.read(address)?, address)?;
+            self.write_trace_line();
+            self.cpu.pc = address.wrapping_add(1);
+            self.instruction_state = InstructionState { instruction, m_cycle: 0, address };
         }
 
-        self.instruction_state.m_cycle += 1;
+        // The cycle count is only updated once the cycle has succeeded, so a failed
+        // cycle can't leave an instruction half-executed.
+        let m_cycle = self.instruction_state.m_cycle + 1;
 
         match &mut self.instruction_state.instruction {
             Instruction::Initial => unreachable!(
@@ -64,98 +225,255 @@ impl Gameboy {
             Instruction::NOP => {},
             Instruction::LD_r_r { to, from } => {
                 if to != from {
-                    *self.system.register8_mut(*to) = self.system.register8(*from);
+                    *self.cpu.register8_mut(*to) = self.cpu.register8(*from);
+                } else if *to == Register8::B {
+                    // LD B,B does nothing, so it's used as a breakpoint by other
+                    // emulators and test ROMs (Mooneye's in particular).
+                    self.software_breakpoint = Some(self.cpu.pc.wrapping_sub(1));
                 }
             },
             Instruction::LD_r_n { to } => {
-                if self.instruction_state.m_cycle == 2 {
-                    *self.system.register8_mut(*to) = self.system.fetch();
+                if m_cycle == 2 {
+                    *self.cpu.register8_mut(*to) = self.cpu.fetch(&mut self.bus)?;
                 }
             },
             Instruction::LD_r_HL { to } => {
-                if self.instruction_state.m_cycle == 2 {
-                    let hl = self.system.register16(Register16::HL);
-                    *self.system.register8_mut(*to) = self.system.random_access(hl);
+                if m_cycle == 2 {
+                    let hl = self.cpu.register16(Register16::HL);
+                    *self.cpu.register8_mut(*to) = self.bus.read(hl)?;
                 }
             },
             Instruction::LD_HL_r { from } => {
-                if self.instruction_state.m_cycle == 2 {
-                    let hl = self.system.register16(Register16::HL);
-                    let r = self.system.register8(*from);
-                    self.system.write_memory(hl, r);
+                if m_cycle == 2 {
+                    let hl = self.cpu.register16(Register16::HL);
+                    let r = self.cpu.register8(*from);
+                    self.bus.write(hl, r);
                 }
             },
-            Instruction::LD_HL_n { data } => match self.instruction_state.m_cycle {
+            Instruction::LD_HL_n { data } => match m_cycle {
                 1 => {},
                 2 => {
-                    *data = self.system.fetch();
+                    *data = self.cpu.fetch(&mut self.bus)?;
                 },
                 3 => {
-                    let hl = self.system.register16(Register16::HL);
-                    self.system.write_memory(hl, *data);
+                    let hl = self.cpu.register16(Register16::HL);
+                    self.bus.write(hl, *data);
                 },
                 _ => unreachable!(),
             },
-            Instruction::JP_nn { address } => match self.instruction_state.m_cycle {
+            Instruction::JP_nn { address } => match m_cycle {
                 1 => {},
                 2 => {
-                    *address = self.system.fetch() as u16;
+                    *address = self.cpu.fetch(&mut self.bus)? as u16;
                 },
                 3 => {
-                    *address |= (self.system.fetch() as u16) << 8;
+                    *address |= (self.cpu.fetch(&mut self.bus)? as u16) << 8;
                 },
                 4 => {
-                    self.system.pc = *address;
+                    self.cpu.pc = *address;
                 },
                 _ => unreachable!(),
             },
+            Instruction::Illegal { opcode } => {
+                let pc = self.cpu.pc.wrapping_sub(1);
+                self.lockup = Some(Lockup { opcode: *opcode, pc });
+            },
         }
+
+        self.instruction_state.m_cycle = m_cycle;
+        self.bus.tick();
+        Ok(())
+    }
+
+    /// Writes a line in the Gameboy Doctor format (see [`crate::trace`]) to
+    /// `trace` before every instruction from now on.
+    pub fn set_trace(&mut self, trace: Box<dyn Write>) {
+        self.trace = Some(trace);
     }
 
-    // If the next cycle is to read an opcode that is unrecognized (read:
-    // unimplemented for now), then return the opcode as a byte so it can be
-    // displayed in an error message to the user without panicking.
-    pub fn peek_instruction_state(&self) -> Result<InstructionState, u8> {
+    /// Flushes the trace, which is otherwise only flushed when the Gameboy is
+    /// dropped.
+    pub fn flush_trace(&mut self) {
+        if let Some(Err(error)) = self.trace.as_mut().map(|trace| trace.flush()) {
+            log::error!("Failed to write trace, tracing stopped: {error}");
+            self.trace = None;
+        }
+    }
+
+    fn write_trace_line(&mut self) {
+        if let Some(mut trace) = self.trace.take() {
+            match writeln!(trace, "{}", TraceLine::new(self)) {
+                Ok(()) => self.trace = Some(trace),
+                Err(error) => log::error!("Failed to write trace, tracing stopped: {error}"),
+            }
+        }
+    }
+
+    /// Runs until the current instruction is finished, or for the whole of the
+    /// next instruction if the last one already is.
+    pub fn step_instruction(&mut self) -> Result<(), EmulationError> {
+        loop {
+            self.cycle()?;
+            if self.instruction_state.is_done() {
+                return Ok(());
+            }
+        }
+    }
+
+    // Returns the instruction that will be executing on the next cycle, or the
+    // error the next cycle would fail with if the next opcode can't be decoded.
+    pub(crate) fn peek_instruction_state(&self) -> Result<InstructionState, EmulationError> {
         let mut state = self.instruction_state.clone();
+        if self.lockup.is_some() {
+            return Ok(state);
+        }
         if state.is_done() {
-            let byte = self.system.rom[self.system.pc as usize];
-            let instruction = Instruction::from_opcode(byte).ok_or(byte)?;
-            state = InstructionState { instruction, m_cycle: 0 };
+            let instruction = decode(self.bus.peek(self.cpu.pc)?, self.cpu.pc)?;
+            state = InstructionState { instruction, m_cycle: 0, address: self.cpu.pc };
         }
         state.m_cycle += 1;
         Ok(state)
     }
 
+    /// Whether the last instruction has finished, so the next cycle starts a
+    /// new one.
+    pub fn is_instruction_done(&self) -> bool {
+        self.instruction_state.is_done()
+    }
+
+    /// Where the instruction that is executing was fetched from, or the last
+    /// one if it has finished.
+    pub fn instruction_address(&self) -> u16 {
+        self.instruction_state.address
+    }
+
     pub fn registers<'a>(&'a self) -> Registers<'a> {
-        Registers { system: &self.system, idx: 0 }
+        Registers { cpu: &self.cpu, idx: 0 }
+    }
+
+    /// Returns the address of the last `LD B,B` executed since this was last
+    /// called, if there was one.
+    ///
+    /// `LD B,B` is used as a software breakpoint, e.g. Mooneye's test ROMs
+    /// execute it once they have finished.
+    pub fn take_software_breakpoint(&mut self) -> Option<u16> {
+        self.software_breakpoint.take()
+    }
+
+    /// Whether the CPU has locked up by executing an illegal opcode, and if so,
+    /// which one.
+    pub fn lockup(&self) -> Option<Lockup> {
+        self.lockup
     }
 
     pub fn pc(&self) -> u16 {
-        self.system.pc
+        self.cpu.pc
+    }
+
+    pub fn sp(&self) -> u16 {
+        self.cpu.sp
+    }
+
+    pub fn cpu(&self) -> &CpuState {
+        &self.cpu
+    }
+
+    /// The CPU's registers, which can be changed between M-cycles.
+    pub fn cpu_mut(&mut self) -> &mut CpuState {
+        &mut self.cpu
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    /// Reads a byte from the memory map without any side effects, using
+    /// [`Bus::peek`].
+    ///
+    /// Unlike a read by the CPU, this isn't seen by the bus, so it never shows
+    /// up in [`Bus::take_access_event`] and never trips a watchpoint. Where
+    /// the CPU's read would fail, e.g. ROM past the end of the cartridge, this
+    /// reads 0xFF instead of returning an error.
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.bus.peek(address).unwrap_or(0xFF)
+    }
+
+    /// Writes a byte to the memory map, as the CPU would.
+    pub fn write_memory(&mut self, address: u16, value: u8) {
+        self.bus.write(address, value);
+    }
+
+    /// Writes a byte to the memory map without any side effects (see
+    /// [`Bus::poke`]).
+    pub fn poke_memory(&mut self, address: u16, value: u8) {
+        self.bus.poke(address, value);
     }
 }
 
-struct System {
-    pc: u16,
-    sp: u16,
-    a: u8,
-    b: u8,
-    c: u8,
-    d: u8,
-    e: u8,
-    f: u8,
-    h: u8,
-    l: u8,
-
-    rom: Vec<u8>,
+fn decode(opcode: u8, pc: u16) -> Result<Instruction, EmulationError> {
+    Instruction::from_opcode(opcode).ok_or(EmulationError::UnimplementedOpcode { opcode, pc })
 }
 
-impl System {
-    fn new(rom: Vec<u8>) -> Self {
-        Self { pc: INITIAL_PC, sp: INITIAL_SP, a: 0, b: 0, c: 0, d: 0, e: 0, f: 0, h: 0, l: 0, rom }
+/// The illegal opcode that locked up the CPU, and where it was.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Lockup {
+    pub opcode: u8,
+    pub pc: u16,
+}
+
+impl fmt::Display for Lockup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cpu locked up by illegal opcode 0x{:02X} @ {:#06X}", self.opcode, self.pc)
+    }
+}
+
+/// How long to run for in headless mode.
+#[derive(Clone, Copy, Debug)]
+pub enum RunLimit {
+    Frames(u64),
+    /// M-cycles.
+    Cycles(u64),
+}
+
+impl RunLimit {
+    /// The limit in M-cycles. So many frames that their cycles don't fit in a
+    /// u64 are as good as forever, so they give u64::MAX.
+    pub fn cycles(self) -> u64 {
+        match self {
+            Self::Frames(frames) => frames.saturating_mul(CYCLES_PER_FRAME as u64),
+            Self::Cycles(cycles) => cycles,
+        }
     }
+}
+
+/// The CPU's registers.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CpuState {
+    pub pc: u16,
+    pub sp: u16,
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub f: u8,
+    pub h: u8,
+    pub l: u8,
+}
 
-    fn register8(&self, register: Register8) -> u8 {
+impl Default for CpuState {
+    /// The state the CPU starts in when a cartridge is inserted.
+    fn default() -> Self {
+        Self { pc: INITIAL_PC, sp: INITIAL_SP, a: 0, b: 0, c: 0, d: 0, e: 0, f: 0, h: 0, l: 0 }
+    }
+}
+
+impl CpuState {
+    pub(crate) fn register8(&self, register: Register8) -> u8 {
         match register {
             Register8::A => self.a,
             Register8::B => self.b,
@@ -168,7 +486,7 @@ impl System {
         }
     }
 
-    fn register8_mut(&mut self, register: Register8) -> &mut u8 {
+    pub(crate) fn register8_mut(&mut self, register: Register8) -> &mut u8 {
         match register {
             Register8::A => &mut self.a,
             Register8::B => &mut self.b,
@@ -181,7 +499,7 @@ impl System {
         }
     }
 
-    fn register16(&self, register: Register16) -> u16 {
+    pub(crate) fn register16(&self, register: Register16) -> u16 {
         match register {
             Register16::AF => (self.a as u16) << 8 | self.f as u16,
             Register16::BC => (self.b as u16) << 8 | self.c as u16,
@@ -190,30 +508,206 @@ impl System {
         }
     }
 
-    // TODO: Implement memory I/O.
+    // The lower 4 bits of F don't exist, so they are always 0.
+    pub(crate) fn set_register16(&mut self, register: Register16, value: u16) {
+        let [high, low] = value.to_be_bytes();
+        match register {
+            Register16::AF => (self.a, self.f) = (high, low & 0xF0),
+            Register16::BC => (self.b, self.c) = (high, low),
+            Register16::DE => (self.d, self.e) = (high, low),
+            Register16::HL => (self.h, self.l) = (high, low),
+        }
+    }
 
-    fn fetch(&mut self) -> u8 {
-        let byte = self.rom[self.pc as usize];
-        self.pc += 1;
-        byte
+    fn fetch(&mut self, bus: &mut impl Bus) -> Result<u8, EmulationError> {
+        let byte = bus.read(self.pc)?;
+        self.pc = self.pc.wrapping_add(1);
+        Ok(byte)
+    }
+}
+
+/// The Gameboy's own hardware: the cartridge, memory and peripherals.
+pub struct System {
+    cartridge: Cartridge,
+    wram: Box<[u8; 0x2000]>,
+    hram: [u8; 0x7F],
+
+    // The internal 16-bit counter that DIV exposes the upper 8 bits of. It is
+    // incremented every T-cycle.
+    system_counter: u16,
+    apu: Apu,
+    ppu: Ppu,
+    joypad: Joypad,
+    serial: Serial,
+    // The last value written to DMA, which is also the upper byte of the source
+    // address of the last OAM DMA transfer.
+    dma: u8,
+
+    // TODO: Dispatch interrupts, for now they are only ever requested.
+    interrupt_flag: u8,
+    interrupt_enable: u8,
+
+    // The last access made through the Bus trait, for the debugger.
+    access_event: Option<AccessEvent>,
+}
+
+impl System {
+    fn new(cartridge: Cartridge) -> Self {
+        Self {
+            cartridge,
+            wram: Box::new([0; 0x2000]),
+            hram: [0; 0x7F],
+            system_counter: 0,
+            apu: Apu::new(),
+            ppu: Ppu::new(),
+            joypad: Joypad::new(),
+            serial: Serial::new(),
+            dma: 0,
+            interrupt_flag: 0,
+            interrupt_enable: 0,
+            access_event: None,
+        }
     }
 
+    // Reads a byte, where ROM past the end of the cartridge reads as 0xFF.
     fn random_access(&self, address: u16) -> u8 {
-        0
+        match address {
+            cartridge::ROM_START..=cartridge::ROM_END => {
+                self.cartridge.read_rom(address).unwrap_or(0xFF)
+            },
+            ppu::VRAM_START..=ppu::VRAM_END | ppu::OAM_START..=ppu::OAM_END => {
+                self.ppu.read(address)
+            },
+            cartridge::RAM_START..=cartridge::RAM_END => self.cartridge.read_ram(address),
+            WRAM_START..=WRAM_END => self.wram[(address - WRAM_START) as usize],
+            ECHO_START..=ECHO_END => self.wram[(address - ECHO_START) as usize],
+            joypad::P1 => self.joypad.read(),
+            serial::SB | serial::SC => self.serial.read(address),
+            DIV => (self.system_counter >> 8) as u8,
+            // The upper 3 bits of IF are unused and always read as 1.
+            IF => self.interrupt_flag | 0xE0,
+            0xFF10..=0xFF3F => self.apu.read(address),
+            DMA => self.dma,
+            ppu::LCDC..=ppu::WX => self.ppu.read(address),
+            HRAM_START..=HRAM_END => self.hram[(address - HRAM_START) as usize],
+            IE => self.interrupt_enable,
+            _ => 0,
+        }
     }
 
-    fn write_memory(&mut self, address: u16, data: u8) {}
+    fn write_memory(&mut self, address: u16, data: u8) {
+        match address {
+            cartridge::ROM_START..=cartridge::ROM_END
+            | cartridge::RAM_START..=cartridge::RAM_END => self.cartridge.write(address, data),
+            ppu::VRAM_START..=ppu::VRAM_END | ppu::OAM_START..=ppu::OAM_END => {
+                self.ppu.write(address, data)
+            },
+            WRAM_START..=WRAM_END => self.wram[(address - WRAM_START) as usize] = data,
+            ECHO_START..=ECHO_END => self.wram[(address - ECHO_START) as usize] = data,
+            joypad::P1 => self.joypad.write(data),
+            serial::SB | serial::SC => self.serial.write(address, data),
+            // Writing any value to DIV resets the whole system counter.
+            DIV => self.set_system_counter(0),
+            IF => self.interrupt_flag = data & 0x1F,
+            0xFF10..=0xFF3F => self.apu.write(address, data),
+            DMA => self.oam_dma(data),
+            ppu::LCDC..=ppu::WX => self.ppu.write(address, data),
+            HRAM_START..=HRAM_END => self.hram[(address - HRAM_START) as usize] = data,
+            IE => self.interrupt_enable = data,
+            _ => {},
+        }
+    }
+
+    // Advances the components that are clocked independently of the CPU by a
+    // single M-cycle.
+    fn tick(&mut self) {
+        self.set_system_counter(self.system_counter.wrapping_add(4));
+        self.apu.tick();
+        self.interrupt_flag |= self.ppu.tick();
+    }
+
+    // Copies 160 bytes from `source` * 0x100 into OAM.
+    //
+    // TODO: On hardware this takes 160 M-cycles, during which the CPU can only
+    // access HRAM. It is done all at once for now.
+    fn oam_dma(&mut self, source: u8) {
+        self.dma = source;
+        let source = (source as u16) << 8;
+        for offset in 0..(ppu::OAM_END - ppu::OAM_START + 1) {
+            let data = self.random_access(source + offset);
+            self.ppu.write(ppu::OAM_START + offset, data);
+        }
+    }
+
+    fn set_system_counter(&mut self, value: u16) {
+        // Bits that were set before and are cleared now, i.e. falling edges.
+        let falling = self.system_counter & !value;
+        self.system_counter = value;
+
+        if falling & FRAME_SEQUENCER_COUNTER_BIT != 0 {
+            self.apu.clock_frame_sequencer();
+        }
+        if falling & SERIAL_COUNTER_BIT != 0 && self.serial.clock() {
+            self.interrupt_flag |= INTERRUPT_SERIAL;
+        }
+    }
+}
+
+impl Bus for System {
+    fn read(&mut self, address: u16) -> Result<u8, EmulationError> {
+        let value = self.peek(address)?;
+        self.access_event =
+            Some(AccessEvent { access: BusAccess::Read { address, value }, old_value: value });
+        Ok(value)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        let old_value = self.random_access(address);
+        self.write_memory(address, value);
+        self.access_event =
+            Some(AccessEvent { access: BusAccess::Write { address, value }, old_value });
+    }
+
+    fn peek(&self, address: u16) -> Result<u8, EmulationError> {
+        if (cartridge::ROM_START..=cartridge::ROM_END).contains(&address) {
+            let len = self.cartridge.rom_len();
+            return self
+                .cartridge
+                .read_rom(address)
+                .ok_or(EmulationError::RomOutOfRange { address, len });
+        }
+        Ok(self.random_access(address))
+    }
+
+    // Most I/O registers do more than store what is written to them, and writes
+    // to ROM go to the MBC, so neither can be poked.
+    fn poke(&mut self, address: u16, value: u8) {
+        let rom = cartridge::ROM_START..=cartridge::ROM_END;
+        if !(IO_START..=IO_END).contains(&address) && !rom.contains(&address) {
+            self.write_memory(address, value);
+        }
+    }
+
+    fn tick(&mut self) {
+        System::tick(self);
+    }
+
+    fn take_access_event(&mut self) -> Option<AccessEvent> {
+        self.access_event.take()
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct InstructionState {
     pub instruction: Instruction,
     pub m_cycle: usize,
+    /// Where the instruction's opcode was fetched from.
+    pub address: u16,
 }
 
 impl Default for InstructionState {
     fn default() -> Self {
-        Self { instruction: Instruction::Initial, m_cycle: 0 }
+        Self { instruction: Instruction::Initial, m_cycle: 0, address: 0 }
     }
 }
 
@@ -238,11 +732,14 @@ pub enum Register8 {
     C,
     D,
     E,
+    // F is not addressable by any 8-bit load, but is kept here so every
+    // register has a name.
     F,
     H,
     L,
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Register16 {
     AF,
     BC,
@@ -251,7 +748,7 @@ pub enum Register16 {
 }
 
 pub struct Registers<'a> {
-    system: &'a System,
+    cpu: &'a CpuState,
     idx: usize,
 }
 
@@ -260,16 +757,16 @@ impl<'a> Iterator for Registers<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         let register = match self.idx {
-            0 => Register { name: "pc", value: RegisterValue::U16(self.system.pc) },
-            1 => Register { name: "sp", value: RegisterValue::U16(self.system.sp) },
-            2 => Register { name: "a", value: RegisterValue::U8(self.system.a) },
-            3 => Register { name: "b", value: RegisterValue::U8(self.system.b) },
-            4 => Register { name: "c", value: RegisterValue::U8(self.system.c) },
-            5 => Register { name: "d", value: RegisterValue::U8(self.system.d) },
-            6 => Register { name: "e", value: RegisterValue::U8(self.system.e) },
-            7 => Register { name: "f", value: RegisterValue::U8(self.system.f) },
-            8 => Register { name: "h", value: RegisterValue::U8(self.system.h) },
-            9 => Register { name: "l", value: RegisterValue::U8(self.system.l) },
+            0 => Register { name: "pc", value: RegisterValue::U16(self.cpu.pc) },
+            1 => Register { name: "sp", value: RegisterValue::U16(self.cpu.sp) },
+            2 => Register { name: "a", value: RegisterValue::U8(self.cpu.a) },
+            3 => Register { name: "b", value: RegisterValue::U8(self.cpu.b) },
+            4 => Register { name: "c", value: RegisterValue::U8(self.cpu.c) },
+            5 => Register { name: "d", value: RegisterValue::U8(self.cpu.d) },
+            6 => Register { name: "e", value: RegisterValue::U8(self.cpu.e) },
+            7 => Register { name: "f", value: RegisterValue::U8(self.cpu.f) },
+            8 => Register { name: "h", value: RegisterValue::U8(self.cpu.h) },
+            9 => Register { name: "l", value: RegisterValue::U8(self.cpu.l) },
             _ => return None,
         };
         self.idx += 1;
@@ -304,20 +801,288 @@ mod test {
     fn register16_combines_8bit_registers() {
         let mut gb = Gameboy::no_cartridge();
 
-        gb.system.a = 0x20;
-        gb.system.f = 0x94;
-        assert_eq!(gb.system.register16(Register16::AF), 0x2094);
+        gb.cpu.a = 0x20;
+        gb.cpu.f = 0x94;
+        assert_eq!(gb.cpu.register16(Register16::AF), 0x2094);
+
+        gb.cpu.b = 0x42;
+        gb.cpu.c = 0x21;
+        assert_eq!(gb.cpu.register16(Register16::BC), 0x4221);
+
+        gb.cpu.d = 0x65;
+        gb.cpu.e = 0xBC;
+        assert_eq!(gb.cpu.register16(Register16::DE), 0x65BC);
+
+        gb.cpu.h = 0x0A;
+        gb.cpu.l = 0xF0;
+        assert_eq!(gb.cpu.register16(Register16::HL), 0x0AF0);
+    }
+
+    #[test]
+    fn div_exposes_upper_byte_of_system_counter_and_resets_on_write() {
+        let mut gb = Gameboy::no_cartridge();
+        for _ in 0..64 {
+            gb.bus.tick();
+        }
+        assert_eq!(gb.bus.random_access(DIV), 0x01);
+
+        gb.bus.write_memory(DIV, 0xAB);
+        assert_eq!(gb.bus.random_access(DIV), 0x00);
+    }
+
+    #[test]
+    fn apu_registers_are_mapped_on_the_bus() {
+        let mut gb = Gameboy::no_cartridge();
+        gb.bus.write_memory(0xFF26, 0x80);
+        gb.bus.write_memory(0xFF24, 0x35);
+        assert_eq!(gb.bus.random_access(0xFF24), 0x35);
+        assert_eq!(gb.bus.random_access(0xFF26), 0xF0);
+    }
+
+    #[test]
+    fn ld_hl_r_is_decoded_and_takes_two_cycles() {
+        let mut rom = vec![0; INITIAL_PC as usize];
+        rom.extend([0x70, 0x71, 0x72, 0x73, 0x74, 0x75, 0x77]);
+        let mut gb = Gameboy::new(rom).unwrap();
+        for pc in INITIAL_PC + 1..=INITIAL_PC + 7 {
+            gb.cycle().unwrap();
+            assert!(matches!(gb.instruction_state.instruction, Instruction::LD_HL_r { .. }));
+            gb.cycle().unwrap();
+            assert!(gb.instruction_state.is_done());
+            assert_eq!(gb.pc(), pc);
+        }
+        assert!(matches!(gb.instruction_state.instruction, Instruction::LD_HL_r {
+            from: Register8::A
+        }));
+    }
+
+    #[test]
+    fn serial_transfer_completes_at_8192_hz_and_requests_interrupt() {
+        let mut gb = Gameboy::no_cartridge();
+        gb.bus.write_memory(serial::SB, 0x42);
+        gb.bus.write_memory(serial::SC, 0x81);
+
+        // 8 bits at 8192 Hz is 1024 M-cycles.
+        for _ in 0..1023 {
+            gb.bus.tick();
+        }
+        assert_eq!(gb.bus.random_access(serial::SC), 0xFF);
+        assert_eq!(gb.bus.random_access(IF), 0xE0);
+
+        gb.bus.tick();
+        assert_eq!(gb.bus.random_access(serial::SB), 0xFF);
+        assert_eq!(gb.bus.random_access(serial::SC), 0x7F);
+        assert_eq!(gb.bus.random_access(IF), 0xE0 | INTERRUPT_SERIAL);
+    }
+
+    #[test]
+    fn execute_headless_stops_at_cycle_limit() {
+        // JP 0x0100, forever.
+        let mut rom = vec![0; INITIAL_PC as usize];
+        rom.extend([0xC3, 0x00, 0x01]);
+        let mut gb = Gameboy::new(rom).unwrap();
+
+        assert!(gb.execute_headless(RunLimit::Cycles(10), None).is_ok());
+        // 2 full JPs and the first 2 cycles of a third, which reads the low byte.
+        assert_eq!(gb.pc(), INITIAL_PC + 2);
+
+        assert!(gb.execute_headless(RunLimit::Frames(1), None).is_ok());
+    }
+
+    #[test]
+    fn run_limit_counts_frames_in_m_cycles() {
+        assert_eq!(RunLimit::Frames(2).cycles(), 2 * 17_556);
+        assert_eq!(RunLimit::Frames(u64::MAX).cycles(), u64::MAX);
+        assert_eq!(RunLimit::Cycles(10).cycles(), 10);
+    }
+
+    #[test]
+    fn execute_headless_stops_at_unimplemented_opcode() {
+        // NOP, NOP, DAA.
+        let mut rom = vec![0; INITIAL_PC as usize];
+        rom.extend([0x00, 0x00, 0x27]);
+        let mut gb = Gameboy::new(rom).unwrap();
+
+        let result = gb.execute_headless(RunLimit::Cycles(100), None);
+        assert!(matches!(
+            result,
+            Err(EmulationError::UnimplementedOpcode { opcode: 0x27, pc: 0x0102 })
+        ));
+        assert_eq!(gb.pc(), INITIAL_PC + 2);
+    }
+
+    #[test]
+    fn illegal_opcode_locks_up_cpu() {
+        // LD B,n, then an illegal opcode followed by LD C,n which is never executed.
+        let mut rom = vec![0; INITIAL_PC as usize];
+        rom.extend([0x06, 0x01, 0xD3, 0x0E, 0x01]);
+        let mut gb = Gameboy::new(rom).unwrap();
+
+        gb.step_instruction().unwrap();
+        assert_eq!(gb.lockup(), None);
+        gb.step_instruction().unwrap();
+        assert_eq!(gb.lockup(), Some(Lockup { opcode: 0xD3, pc: 0x0102 }));
+
+        let counter = gb.bus.system_counter;
+        gb.execute_headless(RunLimit::Cycles(10), None).unwrap();
+        assert_eq!(gb.pc(), 0x0103);
+        assert_eq!(gb.cpu.c, 0);
+        // The rest of the hardware keeps running.
+        assert_eq!(gb.bus.system_counter, counter.wrapping_add(40));
+    }
+
+    #[test]
+    fn ld_b_b_is_a_software_breakpoint() {
+        // LD C,C, LD B,B.
+        let mut rom = vec![0; INITIAL_PC as usize];
+        rom.extend([0x49, 0x40]);
+        let mut gb = Gameboy::new(rom).unwrap();
+
+        gb.step_instruction().unwrap();
+        assert_eq!(gb.take_software_breakpoint(), None);
+        gb.step_instruction().unwrap();
+        assert_eq!(gb.take_software_breakpoint(), Some(0x0101));
+        assert_eq!(gb.take_software_breakpoint(), None);
+    }
+
+    #[test]
+    fn instruction_state_has_the_opcode_address() {
+        // LD B,n, then NOPs.
+        let mut rom = vec![0; INITIAL_PC as usize];
+        rom.extend([0x06, 0x12, 0x00, 0x00]);
+        let mut gb = Gameboy::new(rom).unwrap();
+
+        gb.cycle().unwrap();
+        assert_eq!(gb.instruction_state.address, INITIAL_PC);
+        gb.cycle().unwrap();
+        assert_eq!(gb.instruction_state.address, INITIAL_PC);
+        gb.cycle().unwrap();
+        assert_eq!(gb.instruction_state.address, INITIAL_PC + 2);
+        assert_eq!(gb.peek_instruction_state().unwrap().address, INITIAL_PC + 3);
+    }
+
+    #[test]
+    fn ld_hl_r_writes_register_to_memory() {
+        // LD (HL),B, LD (HL),C, LD (HL),D, LD (HL),E, LD (HL),A, then LD (HL),H
+        // and LD (HL),L which write the address itself.
+        let mut rom = vec![0; INITIAL_PC as usize];
+        rom.extend([0x70, 0x71, 0x72, 0x73, 0x77, 0x74, 0x75]);
+        let mut gb = Gameboy::new(rom).unwrap();
+        gb.cpu.a = 0x0A;
+        gb.cpu.b = 0x0B;
+        gb.cpu.c = 0x0C;
+        gb.cpu.d = 0x0D;
+        gb.cpu.e = 0x0E;
+        gb.cpu.set_register16(Register16::HL, 0x8000);
+
+        for expected in [0x0B, 0x0C, 0x0D, 0x0E, 0x0A, 0x80, 0x00] {
+            gb.step_instruction().unwrap();
+            assert_eq!(gb.read_memory(0x8000), expected);
+        }
+        // Each takes 2 M-cycles.
+        assert_eq!(gb.bus.system_counter, 7 * 2 * 4);
+    }
+
+    #[test]
+    fn failed_cycle_leaves_state_unchanged() {
+        // LD A,n with the operand past the end of the ROM.
+        let mut rom = vec![0; INITIAL_PC as usize];
+        rom.push(0x3E);
+        let mut gb = Gameboy::new(rom).unwrap();
+
+        gb.cycle().unwrap();
+        for _ in 0..2 {
+            let result = gb.cycle();
+            assert!(matches!(result, Err(EmulationError::RomOutOfRange { address: 0x0101, .. })));
+            assert_eq!(gb.pc(), INITIAL_PC + 1);
+            assert_eq!(gb.instruction_state.m_cycle, 1);
+        }
+    }
+
+    #[test]
+    fn wram_and_hram_are_mapped() {
+        let mut gb = Gameboy::no_cartridge();
+        gb.write_memory(0xC000, 0x12);
+        gb.write_memory(0xDFFF, 0x34);
+        gb.write_memory(0xFF80, 0x56);
+        gb.write_memory(0xFFFE, 0x78);
+        assert_eq!(gb.read_memory(0xC000), 0x12);
+        assert_eq!(gb.read_memory(0xDFFF), 0x34);
+        assert_eq!(gb.read_memory(0xFF80), 0x56);
+        assert_eq!(gb.read_memory(0xFFFE), 0x78);
+
+        // Echo RAM.
+        assert_eq!(gb.read_memory(0xE000), 0x12);
+        gb.write_memory(0xFDFF, 0x9A);
+        assert_eq!(gb.read_memory(0xDDFF), 0x9A);
+    }
 
-        gb.system.b = 0x42;
-        gb.system.c = 0x21;
-        assert_eq!(gb.system.register16(Register16::BC), 0x4221);
+    #[test]
+    fn poke_skips_io_registers() {
+        let mut gb = Gameboy::no_cartridge();
+        gb.poke_memory(0x8000, 0x42);
+        assert_eq!(gb.read_memory(0x8000), 0x42);
 
-        gb.system.d = 0x65;
-        gb.system.e = 0xBC;
-        assert_eq!(gb.system.register16(Register16::DE), 0x65BC);
+        gb.bus.set_system_counter(0x1200);
+        gb.poke_memory(DIV, 0);
+        assert_eq!(gb.read_memory(DIV), 0x12);
+    }
+
+    #[test]
+    fn bus_reports_the_last_access() {
+        let mut gb = Gameboy::no_cartridge();
+        gb.poke_memory(0x8000, 0x12);
+        assert_eq!(gb.bus.take_access_event(), None);
 
-        gb.system.h = 0x0A;
-        gb.system.l = 0xF0;
-        assert_eq!(gb.system.register16(Register16::HL), 0x0AF0);
+        gb.write_memory(0x8000, 0x34);
+        assert_eq!(
+            gb.bus.take_access_event(),
+            Some(AccessEvent {
+                access: BusAccess::Write { address: 0x8000, value: 0x34 },
+                old_value: 0x12
+            })
+        );
+        assert_eq!(gb.bus.take_access_event(), None);
+    }
+
+    #[test]
+    fn read_memory_peeks() {
+        let mut gb = Gameboy::new(vec![0x12]).unwrap();
+        assert_eq!(gb.read_memory(0x0000), 0x12);
+        assert_eq!(gb.read_memory(0x0001), 0xFF);
+        assert_eq!(gb.bus.take_access_event(), None);
+    }
+
+    #[test]
+    fn oam_dma_copies_into_oam() {
+        let mut gb = Gameboy::no_cartridge();
+        for offset in 0..0xA0 {
+            gb.bus.write_memory(0x8000 + offset, offset as u8);
+        }
+        gb.bus.write_memory(DMA, 0x80);
+        assert_eq!(gb.bus.random_access(DMA), 0x80);
+        assert_eq!(gb.bus.random_access(ppu::OAM_START), 0x00);
+        assert_eq!(gb.bus.random_access(ppu::OAM_END), 0x9F);
+    }
+
+    #[test]
+    fn ppu_requests_vblank_interrupt() {
+        let mut gb = Gameboy::no_cartridge();
+        gb.bus.write_memory(ppu::LCDC, 0x80);
+        // 144 lines of 114 M-cycles each.
+        for _ in 0..144 * 114 {
+            gb.bus.tick();
+        }
+        assert_eq!(gb.bus.random_access(ppu::LY), 144);
+        assert_eq!(gb.bus.random_access(IF) & ppu::INTERRUPT_VBLANK, ppu::INTERRUPT_VBLANK);
+    }
+
+    #[test]
+    fn pressing_selected_button_requests_joypad_interrupt() {
+        let mut gb = Gameboy::no_cartridge();
+        gb.bus.write_memory(joypad::P1, 0x20);
+        gb.set_button(Button::Up, true);
+        assert_eq!(gb.bus.random_access(joypad::P1), 0xEB);
+        assert_eq!(gb.bus.random_access(IF), 0xE0 | joypad::INTERRUPT_JOYPAD);
     }
 }
@@ -1,7 +1,7 @@
 use crate::gb::Register8;
 
 #[derive(Clone, Debug)]
-#[allow(non_camel_case_types)]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 pub enum Instruction {
     /// Not an instruction, this is the state that the CPU is in when it is
     /// first initialized.
@@ -44,9 +44,15 @@ pub enum Instruction {
     /// Unconditional jump to the absolute address specified by the 16-bit
     /// immediate operand nn.
     JP_nn { address: u16 },
+
+    /// One of the 11 opcodes that aren't assigned to any instruction. Executing
+    /// one locks up the CPU until the Gameboy is turned off.
+    Illegal { opcode: u8 },
 }
 
 impl Instruction {
+    /// Decodes an opcode, returning None if its instruction isn't implemented
+    /// yet.
     pub fn from_opcode(opcode: u8) -> Option<Self> {
         match opcode {
             0x00 => Some(Self::NOP),
@@ -168,14 +174,14 @@ impl Instruction {
             0x6E => Some(Self::LD_r_HL { to: Register8::L }),
             0x6F => Some(Self::LD_r_r { to: Register8::L, from: Register8::A }),
 
-            0x70 => None,
-            0x71 => None,
-            0x72 => None,
-            0x73 => None,
-            0x74 => None,
-            0x75 => None,
+            0x70 => Some(Self::LD_HL_r { from: Register8::B }),
+            0x71 => Some(Self::LD_HL_r { from: Register8::C }),
+            0x72 => Some(Self::LD_HL_r { from: Register8::D }),
+            0x73 => Some(Self::LD_HL_r { from: Register8::E }),
+            0x74 => Some(Self::LD_HL_r { from: Register8::H }),
+            0x75 => Some(Self::LD_HL_r { from: Register8::L }),
             0x76 => None,
-            0x77 => None,
+            0x77 => Some(Self::LD_HL_r { from: Register8::A }),
             0x78 => Some(Self::LD_r_r { to: Register8::A, from: Register8::B }),
             0x79 => Some(Self::LD_r_r { to: Register8::A, from: Register8::C }),
             0x7A => Some(Self::LD_r_r { to: Register8::A, from: Register8::D }),
@@ -273,7 +279,7 @@ impl Instruction {
             0xD0 => None,
             0xD1 => None,
             0xD2 => None,
-            0xD3 => None,
+            0xD3 => Some(Self::Illegal { opcode }),
             0xD4 => None,
             0xD5 => None,
             0xD6 => None,
@@ -281,26 +287,26 @@ impl Instruction {
             0xD8 => None,
             0xD9 => None,
             0xDA => None,
-            0xDB => None,
+            0xDB => Some(Self::Illegal { opcode }),
             0xDC => None,
-            0xDD => None,
+            0xDD => Some(Self::Illegal { opcode }),
             0xDE => None,
             0xDF => None,
 
             0xE0 => None,
             0xE1 => None,
             0xE2 => None,
-            0xE3 => None,
-            0xE4 => None,
+            0xE3 => Some(Self::Illegal { opcode }),
+            0xE4 => Some(Self::Illegal { opcode }),
             0xE5 => None,
             0xE6 => None,
             0xE7 => None,
             0xE8 => None,
             0xE9 => None,
             0xEA => None,
-            0xEB => None,
-            0xEC => None,
-            0xED => None,
+            0xEB => Some(Self::Illegal { opcode }),
+            0xEC => Some(Self::Illegal { opcode }),
+            0xED => Some(Self::Illegal { opcode }),
             0xEE => None,
             0xEF => None,
 
@@ -308,7 +314,7 @@ impl Instruction {
             0xF1 => None,
             0xF2 => None,
             0xF3 => None,
-            0xF4 => None,
+            0xF4 => Some(Self::Illegal { opcode }),
             0xF5 => None,
             0xF6 => None,
             0xF7 => None,
@@ -316,8 +322,8 @@ impl Instruction {
             0xF9 => None,
             0xFA => None,
             0xFB => None,
-            0xFC => None,
-            0xFD => None,
+            0xFC => Some(Self::Illegal { opcode }),
+            0xFD => Some(Self::Illegal { opcode }),
             0xFE => None,
             0xFF => None,
         }
@@ -326,7 +332,7 @@ impl Instruction {
     pub fn cycles(&self) -> usize {
         match self {
             Self::Initial => 0,
-            Self::NOP | Self::LD_r_r { .. } => 1,
+            Self::NOP | Self::LD_r_r { .. } | Self::Illegal { .. } => 1,
             Self::LD_r_n { .. } | Self::LD_r_HL { .. } | Self::LD_HL_r { .. } => 2,
             Self::LD_HL_n { .. } => 3,
             Self::JP_nn { .. } => 4,
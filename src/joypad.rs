@@ -0,0 +1,144 @@
+use std::str::FromStr;
+
+pub const P1: u16 = 0xFF00;
+
+pub const INTERRUPT_JOYPAD: u8 = 1 << 4;
+
+const SELECT_DIRECTIONS: u8 = 1 << 4;
+const SELECT_ACTIONS: u8 = 1 << 5;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Self; 8] = [
+        Self::Right,
+        Self::Left,
+        Self::Up,
+        Self::Down,
+        Self::A,
+        Self::B,
+        Self::Select,
+        Self::Start,
+    ];
+
+    // The bit of `Joypad::pressed` for this button. The lower nibble holds the
+    // directions and the upper nibble holds the actions, each in the same order
+    // as the lower nibble of P1.
+    fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl FromStr for Button {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "right" => Ok(Self::Right),
+            "left" => Ok(Self::Left),
+            "up" => Ok(Self::Up),
+            "down" => Ok(Self::Down),
+            "a" => Ok(Self::A),
+            "b" => Ok(Self::B),
+            "select" => Ok(Self::Select),
+            "start" => Ok(Self::Start),
+            _ => Err(format!("unknown button: {s}")),
+        }
+    }
+}
+
+/// The joypad, mapped to P1 (0xFF00).
+pub struct Joypad {
+    // Bits 4 and 5 of P1, where a 0 selects that group of buttons.
+    select: u8,
+    pressed: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self { select: SELECT_DIRECTIONS | SELECT_ACTIONS, pressed: 0 }
+    }
+
+    pub fn read(&self) -> u8 {
+        // The upper 2 bits are unused, and buttons read as 0 when pressed.
+        0xC0 | self.select | !self.selected_buttons() & 0x0F
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.select = value & (SELECT_DIRECTIONS | SELECT_ACTIONS);
+    }
+
+    /// Presses or releases a button, returning true if the joypad interrupt
+    /// should be requested.
+    pub fn set_button(&mut self, button: Button, pressed: bool) -> bool {
+        let before = self.selected_buttons();
+        if pressed {
+            self.pressed |= button.mask();
+        } else {
+            self.pressed &= !button.mask();
+        }
+        // The interrupt is requested when any selected line goes from high to low,
+        // which is when a selected button is newly pressed.
+        self.selected_buttons() & !before != 0
+    }
+
+    // The pressed buttons in the groups selected by P1, where a 1 means pressed.
+    fn selected_buttons(&self) -> u8 {
+        let mut buttons = 0;
+        if self.select & SELECT_DIRECTIONS == 0 {
+            buttons |= self.pressed & 0x0F;
+        }
+        if self.select & SELECT_ACTIONS == 0 {
+            buttons |= self.pressed >> 4;
+        }
+        buttons
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nothing_selected_reads_all_released() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(Button::A, true);
+        assert_eq!(joypad.read(), 0xFF);
+    }
+
+    #[test]
+    fn selected_group_reports_pressed_buttons_as_zero() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(Button::Down, true);
+        joypad.set_button(Button::Start, true);
+
+        joypad.write(0x20);
+        assert_eq!(joypad.read(), 0xE7);
+
+        joypad.write(0x10);
+        assert_eq!(joypad.read(), 0xD7);
+
+        joypad.write(0x00);
+        assert_eq!(joypad.read(), 0xC7);
+    }
+
+    #[test]
+    fn interrupt_requested_only_for_newly_pressed_selected_buttons() {
+        let mut joypad = Joypad::new();
+        joypad.write(0x10);
+        assert!(!joypad.set_button(Button::Left, true));
+        assert!(joypad.set_button(Button::B, true));
+        assert!(!joypad.set_button(Button::B, true));
+        assert!(!joypad.set_button(Button::B, false));
+    }
+}
@@ -0,0 +1,65 @@
+//! The boyo Gameboy emulator, for embedding in other programs.
+//!
+//! A [`Gameboy`] is constructed from the bytes of a ROM and then driven by
+//! calling one of its stepping methods, which run it for a single M-cycle, a
+//! single instruction, or a whole frame. In between, its input can be set and
+//! its screen, audio, memory and registers can be inspected.
+//!
+//! ```
+//! use boyo::{Button, Gameboy, INITIAL_PC};
+//!
+//! // LD A,$2A, then NOPs.
+//! let mut rom = vec![0; 0x8000];
+//! rom[INITIAL_PC as usize..][..2].copy_from_slice(&[0x3E, 0x2A]);
+//! let mut gameboy = Gameboy::new(rom)?;
+//!
+//! gameboy.step_instruction()?;
+//! assert_eq!(gameboy.pc(), INITIAL_PC + 2);
+//!
+//! gameboy.set_button(Button::Start, true);
+//! gameboy.run_frame()?;
+//! let framebuffer = gameboy.framebuffer();
+//! let audio: Vec<_> = gameboy.audio_samples().collect();
+//! # Ok::<(), boyo::EmulationError>(())
+//! ```
+//!
+//! Stepping returns an [`EmulationError`] instead of panicking when the game
+//! does something that can't be emulated, without changing any state.
+//!
+//! The frontends used by the `boyo` binary are also available: [`terminal`],
+//! the [`debugger`], and `window` with the `window` feature.
+
+mod apu;
+mod bus;
+mod cartridge;
+mod command_history;
+pub mod debugger;
+pub mod disasm;
+mod error;
+mod gb;
+mod instruction;
+mod joypad;
+pub mod link;
+pub mod pacing;
+#[cfg(feature = "window")]
+mod playback;
+mod ppu;
+mod resampler;
+pub mod screenshot;
+mod serial;
+pub mod terminal;
+pub mod trace;
+pub mod wav;
+#[cfg(feature = "window")]
+pub mod window;
+
+pub use crate::apu::{StereoSample, SAMPLE_RATE};
+pub use crate::bus::{AccessEvent, Bus, BusAccess, TestBus};
+pub use crate::error::EmulationError;
+pub use crate::gb::{
+    CpuState, Gameboy, Lockup, Register, RegisterValue, Registers, RunLimit, System, CLOCK_RATE,
+    CYCLES_PER_FRAME, INITIAL_PC,
+};
+pub use crate::joypad::Button;
+pub use crate::ppu::{Framebuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use crate::serial::{Capture, Disconnected, SerialLink};
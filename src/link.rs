@@ -0,0 +1,376 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+use std::{fmt, thread};
+
+use crate::serial::SerialLink;
+
+// Sent by both ends as soon as the connection is established. The last byte is
+// the protocol version.
+const HANDSHAKE: &[u8; 5] = b"BOYO\x02";
+
+const MESSAGE_TRANSFER: u8 = 0x01;
+const MESSAGE_REPLY: u8 = 0x02;
+
+// How long to wait for the other end to answer a transfer before treating the
+// cable as unplugged. The other end only answers the next time its serial clock
+// ticks, which can be most of a frame away when it is sleeping between frames,
+// and much longer when it runs slower than normal speed, so this has to be
+// comfortably longer than one frame. A reply that arrives after this is
+// recognized by its sequence number and ignored.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where a link cable connection lives.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LinkAddress {
+    /// A TCP address, e.g. `127.0.0.1:5000`.
+    Tcp(String),
+    /// A Unix domain socket, given as `unix:<path>`.
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl FromStr for LinkAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            #[cfg(unix)]
+            return Ok(Self::Unix(PathBuf::from(path)));
+            #[cfg(not(unix))]
+            return Err(format!("unix sockets are not supported on this platform: {path}"));
+        }
+        if s.is_empty() {
+            return Err("link address cannot be empty".to_owned());
+        }
+        Ok(Self::Tcp(s.to_owned()))
+    }
+}
+
+impl fmt::Display for LinkAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(address) => write!(f, "{address}"),
+            #[cfg(unix)]
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+// Every message is the kind, the sequence number of the transfer and the data.
+#[derive(Debug)]
+enum Message {
+    // The other end started a transfer using its own clock.
+    Transfer { sequence: u8, data: u8 },
+    // The other end's answer to a transfer we started.
+    Reply { sequence: u8, data: u8 },
+}
+
+/// A link cable to another boyo process, over a TCP or Unix domain socket.
+///
+/// When this end starts a transfer with its internal clock, the outgoing byte
+/// is sent to the other end and the transfer blocks until it replies. When the
+/// other end starts a transfer, this end answers the next time its serial
+/// clock ticks.
+pub struct LinkCable {
+    writer: Box<dyn Write + Send>,
+    messages: Receiver<Message>,
+    // The sequence number of the last transfer this end started, which the
+    // reply has to match.
+    sequence: u8,
+}
+
+impl LinkCable {
+    /// Waits for another boyo process to connect on the given address.
+    pub fn listen(address: &LinkAddress) -> io::Result<Self> {
+        log::info!("Waiting for link cable connection on {address}");
+        match address {
+            LinkAddress::Tcp(address) => {
+                let (stream, peer) = TcpListener::bind(address)?.accept()?;
+                log::info!("Link cable connected to {peer}");
+                Self::from_tcp(stream)
+            },
+            #[cfg(unix)]
+            LinkAddress::Unix(path) => {
+                let listener = UnixListener::bind(path)?;
+                let result = listener.accept();
+                // Nothing else will ever connect, so there's no reason to leave the
+                // socket file lying around.
+                let _ = std::fs::remove_file(path);
+                let (stream, _) = result?;
+                log::info!("Link cable connected");
+                Self::from_unix(stream)
+            },
+        }
+    }
+
+    /// Connects to another boyo process that is listening on the given
+    /// address.
+    pub fn connect(address: &LinkAddress) -> io::Result<Self> {
+        log::info!("Connecting link cable to {address}");
+        match address {
+            LinkAddress::Tcp(address) => Self::from_tcp(TcpStream::connect(address)?),
+            #[cfg(unix)]
+            LinkAddress::Unix(path) => Self::from_unix(UnixStream::connect(path)?),
+        }
+    }
+
+    fn from_tcp(stream: TcpStream) -> io::Result<Self> {
+        // Transfers are a single byte each way, so don't let them sit in a buffer.
+        stream.set_nodelay(true)?;
+        Self::new(stream.try_clone()?, stream)
+    }
+
+    #[cfg(unix)]
+    fn from_unix(stream: UnixStream) -> io::Result<Self> {
+        Self::new(stream.try_clone()?, stream)
+    }
+
+    fn new(
+        mut reader: impl Read + Send + 'static,
+        mut writer: impl Write + Send + 'static,
+    ) -> io::Result<Self> {
+        writer.write_all(HANDSHAKE)?;
+        writer.flush()?;
+
+        let mut handshake = [0; HANDSHAKE.len()];
+        reader.read_exact(&mut handshake)?;
+        if &handshake != HANDSHAKE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the other end of the link cable is not a compatible boyo",
+            ));
+        }
+
+        // Reads happen on their own thread so that polling for transfers started by
+        // the other end never blocks emulation.
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || loop {
+            let mut message = [0; 3];
+            if let Err(error) = reader.read_exact(&mut message) {
+                log::info!("Link cable disconnected: {error}");
+                return;
+            }
+            let message = match message {
+                [MESSAGE_TRANSFER, sequence, data] => Message::Transfer { sequence, data },
+                [MESSAGE_REPLY, sequence, data] => Message::Reply { sequence, data },
+                [kind, ..] => {
+                    log::warn!("Link cable received unknown message kind 0x{kind:02X}");
+                    continue;
+                },
+            };
+            if sender.send(message).is_err() {
+                return;
+            }
+        });
+
+        Ok(Self { writer: Box::new(writer), messages, sequence: 0 })
+    }
+
+    fn send(&mut self, kind: u8, sequence: u8, data: u8) -> io::Result<()> {
+        self.writer.write_all(&[kind, sequence, data])?;
+        self.writer.flush()
+    }
+}
+
+impl SerialLink for LinkCable {
+    fn exchange(&mut self, data: u8) -> u8 {
+        // Any reply still queued up is for a transfer that already timed out, and
+        // any transfer means both ends are about to clock at the same time (see
+        // below).
+        while let Ok(message) = self.messages.try_recv() {
+            if let Message::Transfer { sequence, .. } = message {
+                let _ = self.send(MESSAGE_REPLY, sequence, 0xFF);
+            }
+        }
+
+        self.sequence = self.sequence.wrapping_add(1);
+        if let Err(error) = self.send(MESSAGE_TRANSFER, self.sequence, data) {
+            log::warn!("Link cable transfer failed: {error}");
+            return 0xFF;
+        }
+
+        loop {
+            match self.messages.recv_timeout(REPLY_TIMEOUT) {
+                Ok(Message::Reply { sequence, data }) if sequence == self.sequence => return data,
+                Ok(Message::Reply { sequence, .. }) => {
+                    log::debug!("Ignoring late link cable reply to transfer {sequence}");
+                },
+                // Both ends started a transfer with their own clock at the same time.
+                // Neither end is listening to the other's clock, so both read 0xFF.
+                Ok(Message::Transfer { sequence, .. }) => {
+                    let _ = self.send(MESSAGE_REPLY, sequence, 0xFF);
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    log::warn!("Link cable transfer timed out");
+                    return 0xFF;
+                },
+                Err(RecvTimeoutError::Disconnected) => return 0xFF,
+            }
+        }
+    }
+
+    fn poll_external(&mut self, data: u8) -> Option<u8> {
+        loop {
+            match self.messages.try_recv() {
+                Ok(Message::Transfer { sequence, data: received }) => {
+                    if let Err(error) = self.send(MESSAGE_REPLY, sequence, data) {
+                        log::warn!("Link cable transfer failed: {error}");
+                    }
+                    return Some(received);
+                },
+                // A reply to a transfer that already timed out.
+                Ok(Message::Reply { sequence, .. }) => {
+                    log::debug!("Ignoring late link cable reply to transfer {sequence}");
+                },
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn connect_with_retry(address: &LinkAddress) -> LinkCable {
+        let start = Instant::now();
+        loop {
+            match LinkCable::connect(address) {
+                Ok(cable) => return cable,
+                Err(error) if start.elapsed() > Duration::from_secs(5) => {
+                    panic!("failed to connect: {}", error)
+                },
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    }
+
+    fn poll_until_transfer(cable: &mut LinkCable, data: u8) -> u8 {
+        let start = Instant::now();
+        loop {
+            if let Some(received) = cable.poll_external(data) {
+                return received;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "no transfer received");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn parse_link_address() {
+        assert_eq!("127.0.0.1:5000".parse(), Ok(LinkAddress::Tcp("127.0.0.1:5000".to_owned())));
+        #[cfg(unix)]
+        assert_eq!("unix:/tmp/boyo.sock".parse(), Ok(LinkAddress::Unix("/tmp/boyo.sock".into())));
+        assert!("".parse::<LinkAddress>().is_err());
+    }
+
+    #[test]
+    fn tcp_loopback_handshake_and_transfer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = LinkAddress::Tcp(listener.local_addr().unwrap().to_string());
+        let peer = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut cable = LinkCable::from_tcp(stream).unwrap();
+            poll_until_transfer(&mut cable, 0xCD)
+        });
+
+        let mut cable = LinkCable::connect(&address).unwrap();
+        assert_eq!(cable.exchange(0xAB), 0xCD);
+        assert_eq!(peer.join().unwrap(), 0xAB);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_loopback_handshake_and_transfer() {
+        let path = std::env::temp_dir().join(format!("boyo-link-{}.sock", std::process::id()));
+        let address = LinkAddress::Unix(path.clone());
+        let listen_address = address.clone();
+        let peer = thread::spawn(move || {
+            let mut cable = LinkCable::listen(&listen_address).unwrap();
+            cable.exchange(0x12)
+        });
+
+        let mut cable = connect_with_retry(&address);
+        assert_eq!(poll_until_transfer(&mut cable, 0x34), 0x12);
+        assert_eq!(peer.join().unwrap(), 0x34);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn waits_for_slow_replies() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = LinkAddress::Tcp(listener.local_addr().unwrap().to_string());
+        let peer = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut cable = LinkCable::from_tcp(stream).unwrap();
+            // Several frames, like a peer that is sleeping between frames at a
+            // quarter of normal speed.
+            thread::sleep(Duration::from_millis(100));
+            poll_until_transfer(&mut cable, 0xCD)
+        });
+
+        let mut cable = LinkCable::connect(&address).unwrap();
+        assert_eq!(cable.exchange(0xAB), 0xCD);
+        assert_eq!(peer.join().unwrap(), 0xAB);
+    }
+
+    #[test]
+    fn late_replies_are_ignored() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = LinkAddress::Tcp(listener.local_addr().unwrap().to_string());
+        let peer = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(HANDSHAKE).unwrap();
+            let mut handshake = [0; HANDSHAKE.len()];
+            stream.read_exact(&mut handshake).unwrap();
+
+            let mut transfer = [0; 3];
+            stream.read_exact(&mut transfer).unwrap();
+            let [_, sequence, _] = transfer;
+            // A reply to an earlier transfer, then the real one.
+            stream.write_all(&[MESSAGE_REPLY, sequence.wrapping_sub(1), 0x11]).unwrap();
+            stream.write_all(&[MESSAGE_REPLY, sequence, 0x22]).unwrap();
+            transfer
+        });
+
+        let mut cable = LinkCable::connect(&address).unwrap();
+        assert_eq!(cable.exchange(0x33), 0x22);
+        assert_eq!(peer.join().unwrap(), [MESSAGE_TRANSFER, 1, 0x33]);
+    }
+
+    #[test]
+    fn handshake_rejects_incompatible_peer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let peer = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"HELLO").unwrap();
+        });
+
+        let error = LinkCable::from_tcp(TcpStream::connect(address).unwrap()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        peer.join().unwrap();
+    }
+
+    #[test]
+    fn simultaneous_transfers_both_read_ff() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = LinkAddress::Tcp(listener.local_addr().unwrap().to_string());
+        let peer = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            LinkCable::from_tcp(stream).unwrap().exchange(0x11)
+        });
+
+        let mut cable = LinkCable::connect(&address).unwrap();
+        assert_eq!(cable.exchange(0x22), 0xFF);
+        assert_eq!(peer.join().unwrap(), 0xFF);
+    }
+}
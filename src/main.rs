@@ -1,14 +1,21 @@
-mod command_history;
-mod debugger;
-mod gb;
-mod instruction;
-
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
 use std::num::ParseIntError;
+use std::path::{Path, PathBuf};
 
-use clap::{ArgGroup, Parser};
+use boyo::debugger::{run_terminal_debugger, Debugger, Symbols};
+use boyo::link::{LinkAddress, LinkCable};
+use boyo::pacing::Speed;
+use boyo::screenshot::{self, Palette};
+use boyo::terminal::{self, ColorMode};
+use boyo::trace::{self, DiffOutcome};
+use boyo::wav::WavRecorder;
+#[cfg(feature = "window")]
+use boyo::window;
+use boyo::{disasm, Capture, Disconnected, Gameboy, Register, RunLimit, SerialLink};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 
-use crate::debugger::{run_terminal_debugger, Debugger};
-use crate::gb::Gameboy;
+const ROM_BANK_SIZE: usize = 0x4000;
 
 #[derive(Parser)]
 #[command(
@@ -16,12 +23,21 @@ use crate::gb::Gameboy;
     author = "Mathew Horner <mathewhorner456@gmail.com>",
     about = "A cycle-accurate, efficient, and memory safe emulator for the Gameboy and Gameboy Advance."
 )]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 #[command(group(
     ArgGroup::new("input")
         .required(true)
         .args(&["rom_path", "raw"])
 ))]
+#[command(group(
+    ArgGroup::new("limit")
+        .args(&["frames", "cycles"])
+        .requires("headless")
+))]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to a Gameboy ROM file.
     rom_path: Option<String>,
 
@@ -32,6 +48,184 @@ struct Cli {
     #[arg(short, long, value_parser = parse_u8)]
     /// Raw ROM data to run with.
     raw: Option<Vec<u8>>,
+
+    /// Run as fast as possible without any output, for a number of --frames or
+    /// --cycles. Exits with status 0 if the limit was reached, or 1 if
+    /// emulation failed first.
+    #[arg(long, conflicts_with = "debug", requires = "limit")]
+    headless: bool,
+
+    /// Number of frames to run for in headless mode.
+    #[arg(long)]
+    frames: Option<u64>,
+
+    /// Number of M-cycles to run for in headless mode.
+    #[arg(long)]
+    cycles: Option<u64>,
+
+    /// Print the contents of all cpu registers when headless mode exits.
+    #[arg(long, requires = "headless")]
+    dump_registers: bool,
+
+    /// Save the last frame to a PNG or PPM file (depending on the extension)
+    /// when headless mode exits.
+    #[arg(long, value_name = "FILE", requires = "headless")]
+    screenshot: Option<PathBuf>,
+
+    /// Play in the terminal, drawing the screen with half-block characters and
+    /// reading the joypad from the keyboard. Works over SSH.
+    #[arg(long, conflicts_with_all = ["debug", "headless"])]
+    terminal: bool,
+
+    /// Play in a native window, with audio.
+    #[cfg(feature = "window")]
+    #[arg(long, conflicts_with_all = ["debug", "headless", "terminal"])]
+    window: bool,
+
+    /// How many times larger than the original screen the window is.
+    #[cfg(feature = "window")]
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(1..=8), requires = "window")]
+    scale: u8,
+
+    /// Keys for the joypad buttons in --window mode, as comma separated
+    /// <button>=<key> pairs (e.g. a=k,b=j,start=space). Unlisted buttons keep
+    /// their defaults: the arrow keys, x, z, backspace and enter.
+    #[cfg(feature = "window")]
+    #[arg(long, value_name = "BINDINGS", requires = "window")]
+    keymap: Option<window::KeyMap>,
+
+    /// Colors to draw with in --terminal mode. Defaults to truecolor if the
+    /// COLORTERM environment variable says the terminal supports it.
+    #[arg(long, value_enum, requires = "terminal")]
+    terminal_colors: Option<ColorMode>,
+
+    /// How fast to run compared to a real Gameboy, from 0.25 to 8, or
+    /// unlimited. Can be changed while running with - and + in the terminal,
+    /// or - and = in the window.
+    #[arg(long, default_value_t = Speed::NORMAL, conflicts_with_all = ["debug", "headless"])]
+    speed: Speed,
+
+    /// Colors used for screenshots and the terminal or window display: dmg,
+    /// grayscale, or 4 comma separated hex colors from lightest to darkest
+    /// (e.g. #FFFFFF,#AAAAAA,#555555,#000000).
+    #[arg(long, default_value = "dmg")]
+    palette: Palette,
+
+    /// What to plug into the serial port.
+    #[arg(long, value_enum, default_value_t = SerialBackend::None)]
+    serial: SerialBackend,
+
+    /// Connect the serial port to another boyo process with a link cable,
+    /// either by waiting for it to connect (listen:<address>) or by
+    /// connecting to it (connect:<address>). The address is host:port for
+    /// TCP, or unix:<path> for a Unix domain socket.
+    #[arg(long, value_name = "MODE:ADDRESS", value_parser = parse_link, conflicts_with = "serial")]
+    link: Option<Link>,
+
+    /// A symbol file for the debugger, in the format written by RGBDS. Defaults
+    /// to the ROM's path with a .sym extension, if there is a file there.
+    #[arg(long, value_name = "FILE", requires = "debug")]
+    symbols: Option<PathBuf>,
+
+    /// Write the emulated audio to a 16-bit stereo PCM WAV file.
+    #[arg(long, value_name = "FILE", conflicts_with = "debug")]
+    audio_out: Option<PathBuf>,
+
+    /// Write the registers before every instruction to a file, in the format
+    /// of Gameboy Doctor, to compare with traces from other emulators.
+    #[arg(long, value_name = "FILE")]
+    trace: Option<PathBuf>,
+
+    /// Sample rate of the WAV file written by --audio-out.
+    #[arg(long, value_enum, default_value_t = SampleRate::Hz44100, requires = "audio_out")]
+    audio_sample_rate: SampleRate,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a ROM while comparing the registers before every instruction with a
+    /// reference trace in the Gameboy Doctor format (see --trace), stopping at
+    /// the first line that differs.
+    TraceDiff {
+        /// Path to a Gameboy ROM file.
+        rom: PathBuf,
+
+        /// Path to the reference trace.
+        reference: PathBuf,
+
+        /// Number of matching lines to show before the first difference.
+        #[arg(long, default_value_t = 5)]
+        context: usize,
+    },
+
+    /// Disassemble part of a ROM, printing every instruction with its address
+    /// and raw bytes.
+    #[command(group(ArgGroup::new("part").required(true).args(["range", "bank"])))]
+    Disasm {
+        /// Path to a Gameboy ROM file.
+        rom: PathBuf,
+
+        /// The addresses to disassemble, as <start>-<end> in hex (e.g.
+        /// 0150-01FF), where both ends are included.
+        #[arg(long, value_parser = parse_address_range)]
+        range: Option<(u16, u16)>,
+
+        /// A 16 KiB ROM bank to disassemble. Bank 0 is at 0000-3FFF, and every
+        /// other bank is shown at 4000-7FFF where it is mapped when selected.
+        #[arg(long)]
+        bank: Option<usize>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SerialBackend {
+    /// Nothing is connected, every byte received is 0xFF.
+    None,
+    /// Print every byte sent by the game to stdout, which is how most test
+    /// ROMs report their results.
+    Stdout,
+}
+
+impl SerialBackend {
+    fn link(self) -> Box<dyn SerialLink> {
+        match self {
+            Self::None => Box::new(Disconnected),
+            Self::Stdout => Box::new(Capture::stdout()),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Link {
+    Listen(LinkAddress),
+    Connect(LinkAddress),
+}
+
+fn parse_link(input: &str) -> Result<Link, String> {
+    if let Some(address) = input.strip_prefix("listen:") {
+        Ok(Link::Listen(address.parse()?))
+    } else if let Some(address) = input.strip_prefix("connect:") {
+        Ok(Link::Connect(address.parse()?))
+    } else {
+        Err("expected listen:<address> or connect:<address>".to_owned())
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SampleRate {
+    #[value(name = "44100")]
+    Hz44100,
+    #[value(name = "48000")]
+    Hz48000,
+}
+
+impl SampleRate {
+    fn hz(self) -> u32 {
+        match self {
+            Self::Hz44100 => 44_100,
+            Self::Hz48000 => 48_000,
+        }
+    }
 }
 
 fn parse_u8(input: &str) -> Result<u8, ParseIntError> {
@@ -42,31 +236,189 @@ fn parse_u8(input: &str) -> Result<u8, ParseIntError> {
     }
 }
 
+fn parse_address_range(input: &str) -> Result<(u16, u16), String> {
+    let parse_address = |address: &str| {
+        let address = address.strip_prefix("0x").unwrap_or(address);
+        u16::from_str_radix(address, 16).map_err(|_| format!("invalid address: {address}"))
+    };
+    let (start, end) = input.split_once('-').ok_or("expected <start>-<end>")?;
+    let (start, end) = (parse_address(start)?, parse_address(end)?);
+    if start > end {
+        return Err("the start of the range is after the end".to_owned());
+    }
+    Ok((start, end))
+}
+
 fn main() {
     env_logger::init();
     let cli = Cli::parse();
-    let rom = match cli.rom_path {
-        Some(path) => std::fs::read(&path).expect("failed to read ROM file"),
+    if let Some(command) = cli.command {
+        run_command(command);
+        return;
+    }
+
+    let gameboy = match &cli.rom_path {
+        Some(path) => Gameboy::open(path),
         None => {
             // The system does not start execution at address=0, so we need to pad 0s until
             // we reach the starting position of the PC.
-            let mut rom = vec![0; crate::gb::INITIAL_PC as usize];
+            let mut rom = vec![0; boyo::INITIAL_PC as usize];
 
             // raw should never be None here because we put it in an ArgGroup with
             // rom_path. If rom_path is not provided, raw should be required.
             rom.extend(cli.raw.unwrap());
 
-            rom
+            Gameboy::new(rom)
         },
     };
-    let gameboy = Gameboy::new(rom);
+    let mut gameboy = gameboy.unwrap_or_else(|error| exit_with_error(error));
+    match cli.link {
+        Some(Link::Listen(address)) => {
+            let cable = LinkCable::listen(&address).unwrap_or_else(|error| {
+                exit_with_error(format!("failed to accept link cable connection: {error}"))
+            });
+            gameboy.set_serial_link(Box::new(cable));
+        },
+        Some(Link::Connect(address)) => {
+            let cable = LinkCable::connect(&address).unwrap_or_else(|error| {
+                exit_with_error(format!("failed to connect link cable: {error}"))
+            });
+            gameboy.set_serial_link(Box::new(cable));
+        },
+        None => gameboy.set_serial_link(cli.serial.link()),
+    }
+
+    if let Some(path) = cli.trace {
+        let file = File::create(path).unwrap_or_else(|error| {
+            exit_with_error(format!("failed to create trace file: {error}"))
+        });
+        gameboy.set_trace(Box::new(BufWriter::new(file)));
+    }
+
+    let sample_rate = cli.audio_sample_rate.hz();
+    let audio_out = cli.audio_out.map(|path| {
+        WavRecorder::create(&path, sample_rate).unwrap_or_else(|error| {
+            exit_with_error(format!("failed to create audio output file: {error}"))
+        })
+    });
+
+    #[cfg(feature = "window")]
+    if cli.window {
+        log::info!("Starting boyo in window mode");
+        let key_map = cli.keymap.unwrap_or_default();
+        if let Err(error) =
+            window::run(gameboy, cli.palette, cli.scale as usize, key_map, cli.speed, audio_out)
+        {
+            exit_with_error(error);
+        }
+        return;
+    }
 
-    if !cli.debug {
+    if cli.headless {
+        log::info!("Starting boyo in headless mode");
+        // The "limit" ArgGroup guarantees exactly one of these is provided.
+        let limit = match (cli.frames, cli.cycles) {
+            (Some(frames), _) => RunLimit::Frames(frames),
+            (_, Some(cycles)) => RunLimit::Cycles(cycles),
+            (None, None) => unreachable!(),
+        };
+        let result = gameboy.execute_headless(limit, audio_out);
+        if cli.dump_registers {
+            gameboy.registers().for_each(|Register { name, value }| println!("{name}: {value:#X}"));
+        }
+        if let Some(path) = cli.screenshot {
+            if let Err(error) = screenshot::save(path, gameboy.framebuffer(), cli.palette) {
+                exit_with_error(format!("failed to save screenshot: {error}"));
+            }
+        }
+        if let Some(lockup) = gameboy.lockup() {
+            eprintln!("{lockup}");
+        }
+        if let Err(error) = result {
+            exit_with_error(error);
+        }
+    } else if cli.terminal {
+        log::info!("Starting boyo in terminal mode");
+        let color_mode = cli.terminal_colors.unwrap_or_else(ColorMode::detect);
+        if let Err(error) = terminal::run(gameboy, cli.palette, color_mode, cli.speed, audio_out) {
+            exit_with_error(error);
+        }
+    } else if !cli.debug {
         log::info!("Starting boyo in execution mode");
-        gameboy.execute();
+        exit_with_error(gameboy.execute(cli.speed, audio_out));
     } else {
         log::info!("Starting boyo in terminal debug mode");
-        let debugger = Debugger::new(gameboy);
+        let mut debugger = Debugger::new(gameboy);
+        debugger.set_palette(cli.palette);
+        if let Some(path) = &cli.symbols {
+            let symbols = Symbols::load(path).unwrap_or_else(|error| {
+                exit_with_error(format!("failed to read {}: {error}", path.display()))
+            });
+            debugger.set_symbols(symbols);
+        } else if let Some(rom_path) = &cli.rom_path {
+            // The symbol file next to the ROM is only used if it's there, so it not
+            // being readable isn't worth stopping for.
+            let path = Path::new(rom_path).with_extension("sym");
+            if path.exists() {
+                match Symbols::load(&path) {
+                    Ok(symbols) => debugger.set_symbols(symbols),
+                    Err(error) => log::warn!(
+                        "Failed to read {}, continuing without symbols: {error}",
+                        path.display()
+                    ),
+                }
+            }
+        }
         run_terminal_debugger(debugger);
     }
 }
+
+fn run_command(command: Command) {
+    match command {
+        Command::TraceDiff { rom, reference, context } => {
+            let mut gameboy = Gameboy::open(rom).unwrap_or_else(|error| exit_with_error(error));
+            let reference = File::open(reference).unwrap_or_else(|error| {
+                exit_with_error(format!("failed to open reference trace: {error}"))
+            });
+            match trace::diff(&mut gameboy, BufReader::new(reference), context) {
+                Ok(DiffOutcome::Matched { lines }) => println!("all {lines} lines match"),
+                Ok(DiffOutcome::Mismatch(mismatch)) => {
+                    println!("{mismatch}");
+                    std::process::exit(1);
+                },
+                Err(error) => exit_with_error(error),
+            }
+        },
+        Command::Disasm { rom, range, bank } => {
+            let rom = fs::read(rom)
+                .unwrap_or_else(|error| exit_with_error(format!("failed to read ROM: {error}")));
+            // The "part" ArgGroup guarantees exactly one of these is provided.
+            let (offset, start, end) = match (range, bank) {
+                (Some((start, end)), _) => (0, start, end),
+                (_, Some(0)) => (0, 0x0000, 0x3FFF),
+                (_, Some(bank)) => {
+                    // Bank 1 is at offset 0x4000 in the ROM and mapped at 0x4000, and so on.
+                    let offset = bank
+                        .checked_mul(ROM_BANK_SIZE)
+                        .and_then(|offset| offset.checked_sub(0x4000))
+                        .unwrap_or_else(|| exit_with_error(format!("bank {bank} is too large")));
+                    (offset, 0x4000, 0x7FFF)
+                },
+                (None, None) => unreachable!(),
+            };
+            if offset.checked_add(end as usize).is_none_or(|last| last >= rom.len()) {
+                exit_with_error(format!("the ROM is only {:#X} bytes", rom.len()));
+            }
+            // An instruction at the end can have operands past the end of the ROM.
+            let read = |address: u16| rom.get(offset + address as usize).copied().unwrap_or(0xFF);
+            for instruction in disasm::disassemble_range(start, end, read) {
+                println!("{instruction}");
+            }
+        },
+    }
+}
+
+fn exit_with_error(error: impl std::fmt::Display) -> ! {
+    eprintln!("error: {error}");
+    std::process::exit(1);
+}
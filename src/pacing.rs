@@ -0,0 +1,184 @@
+use std::fmt;
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::gb::{CLOCK_RATE, CYCLES_PER_FRAME};
+
+// How long a frame takes on real hardware, about 16.74 ms (59.73 Hz).
+const FRAME_SECONDS: f64 = (CYCLES_PER_FRAME * 4) as f64 / CLOCK_RATE as f64;
+
+// If emulation falls this far behind (e.g. because the process was suspended),
+// the schedule is reset instead of running flat out until it has caught up.
+const MAX_LAG: Duration = Duration::from_millis(250);
+
+// The speeds stepped through by `Speed::faster` and `Speed::slower`.
+const SPEED_STEPS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+
+/// How fast to run, relative to real hardware.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Speed {
+    Multiplier(f64),
+    /// As fast as possible.
+    Unlimited,
+}
+
+impl Speed {
+    pub const NORMAL: Self = Self::Multiplier(1.0);
+
+    /// The next step up, where the step after 8x is unlimited.
+    pub fn faster(self) -> Self {
+        match self {
+            Self::Multiplier(current) => SPEED_STEPS
+                .iter()
+                .find(|step| **step > current)
+                .map_or(Self::Unlimited, |step| Self::Multiplier(*step)),
+            Self::Unlimited => Self::Unlimited,
+        }
+    }
+
+    /// The next step down, where the step before unlimited is 8x.
+    pub fn slower(self) -> Self {
+        let current = match self {
+            Self::Multiplier(current) => current,
+            Self::Unlimited => f64::INFINITY,
+        };
+        let step =
+            SPEED_STEPS.iter().rev().find(|step| **step < current).unwrap_or(&SPEED_STEPS[0]);
+        Self::Multiplier(*step)
+    }
+}
+
+impl FromStr for Speed {
+    type Err = String;
+
+    /// Parses either a multiplier from 0.25 to 8 (optionally followed by `x`),
+    /// or `unlimited`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "unlimited" {
+            return Ok(Self::Unlimited);
+        }
+        match s.strip_suffix('x').unwrap_or(s).parse::<f64>() {
+            Ok(multiplier) if (0.25..=8.0).contains(&multiplier) => {
+                Ok(Self::Multiplier(multiplier))
+            },
+            _ => Err("expected a multiplier from 0.25 to 8, or unlimited".to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Multiplier(multiplier) => write!(f, "{multiplier}x"),
+            Self::Unlimited => write!(f, "unlimited"),
+        }
+    }
+}
+
+/// Keeps frames in time with the real hardware's clock, scaled by a speed
+/// multiplier.
+///
+/// Every frame is scheduled relative to when the current speed was set, rather
+/// than relative to the previous frame, so time lost to sleeping too long or to
+/// a slow frame is made up for instead of building up.
+pub struct FramePacer {
+    speed: Speed,
+    start: Instant,
+    frames: u64,
+    last_displayed: Instant,
+}
+
+impl FramePacer {
+    pub fn new(speed: Speed) -> Self {
+        let now = Instant::now();
+        Self { speed, start: now, frames: 0, last_displayed: now }
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+        self.restart();
+    }
+
+    /// Waits until the frame that was just emulated is due, returning whether
+    /// it should be displayed.
+    ///
+    /// Every frame is displayed unless the speed is unlimited, in which case
+    /// frames are only displayed as often as on real hardware, so drawing
+    /// doesn't slow down emulation.
+    pub fn next_frame(&mut self) -> bool {
+        let multiplier = match self.speed {
+            Speed::Multiplier(multiplier) => multiplier,
+            Speed::Unlimited => {
+                let now = Instant::now();
+                let display = now - self.last_displayed >= Duration::from_secs_f64(FRAME_SECONDS);
+                if display {
+                    self.last_displayed = now;
+                }
+                return display;
+            },
+        };
+
+        self.frames += 1;
+        let due = self.start + frame_due(self.frames, multiplier);
+        let now = Instant::now();
+        if due > now {
+            sleep(due - now);
+        } else if now - due > MAX_LAG {
+            log::debug!("Emulation fell {:?} behind, resetting frame pacing", now - due);
+            self.restart();
+        }
+        true
+    }
+
+    fn restart(&mut self) {
+        self.start = Instant::now();
+        self.frames = 0;
+    }
+}
+
+// How long after the start of the schedule the end of the given frame is.
+fn frame_due(frames: u64, multiplier: f64) -> Duration {
+    Duration::from_secs_f64(frames as f64 * FRAME_SECONDS / multiplier)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frames_are_paced_by_the_real_clock() {
+        // There are exactly 4194304 / 70224 frames per second.
+        let due = frame_due(CLOCK_RATE as u64, 1.0);
+        assert!(due.abs_diff(Duration::from_secs(70_224)) < Duration::from_millis(1));
+
+        let due = frame_due(CLOCK_RATE as u64, 4.0);
+        assert!(due.abs_diff(Duration::from_secs(70_224 / 4)) < Duration::from_millis(1));
+    }
+
+    #[test]
+    fn parse_speed() {
+        assert_eq!("0.25".parse(), Ok(Speed::Multiplier(0.25)));
+        assert_eq!("8".parse(), Ok(Speed::Multiplier(8.0)));
+        assert_eq!("2x".parse(), Ok(Speed::Multiplier(2.0)));
+        assert_eq!("unlimited".parse(), Ok(Speed::Unlimited));
+        assert!("0.1".parse::<Speed>().is_err());
+        assert!("16".parse::<Speed>().is_err());
+    }
+
+    #[test]
+    fn speed_steps() {
+        assert_eq!(Speed::NORMAL.faster(), Speed::Multiplier(2.0));
+        assert_eq!(Speed::Multiplier(1.5).faster(), Speed::Multiplier(2.0));
+        assert_eq!(Speed::Multiplier(8.0).faster(), Speed::Unlimited);
+        assert_eq!(Speed::Unlimited.faster(), Speed::Unlimited);
+
+        assert_eq!(Speed::Unlimited.slower(), Speed::Multiplier(8.0));
+        assert_eq!(Speed::Multiplier(1.5).slower(), Speed::NORMAL);
+        assert_eq!(Speed::Multiplier(0.25).slower(), Speed::Multiplier(0.25));
+    }
+}
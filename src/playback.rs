@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, Sample, SampleFormat, SizedSample, Stream, StreamConfig};
+
+use crate::apu::{self, StereoSample};
+use crate::resampler::Resampler;
+
+// Samples queued for more than this long are dropped, so the audio doesn't
+// fall further and further behind the picture if emulation runs fast.
+const MAX_LATENCY_MS: u32 = 100;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PlaybackError {
+    #[error("no audio output device available")]
+    NoDevice,
+    #[error("unsupported sample format: {0}")]
+    UnsupportedFormat(SampleFormat),
+    #[error(transparent)]
+    DefaultConfig(#[from] cpal::DefaultStreamConfigError),
+    #[error(transparent)]
+    BuildStream(#[from] cpal::BuildStreamError),
+    #[error(transparent)]
+    PlayStream(#[from] cpal::PlayStreamError),
+}
+
+/// Plays APU output through the default audio device.
+pub struct AudioPlayer {
+    // Audio stops when the stream is dropped.
+    _stream: Stream,
+    queue: Arc<Mutex<VecDeque<StereoSample>>>,
+    max_queued: usize,
+    resampler: Resampler,
+    buffer: Vec<StereoSample>,
+}
+
+impl AudioPlayer {
+    pub fn open() -> Result<Self, PlaybackError> {
+        let device = cpal::default_host().default_output_device().ok_or(PlaybackError::NoDevice)?;
+        let supported = device.default_output_config()?;
+        let config = supported.config();
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, queue.clone())?,
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, queue.clone())?,
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, queue.clone())?,
+            format => return Err(PlaybackError::UnsupportedFormat(format)),
+        };
+        stream.play()?;
+
+        Ok(Self {
+            _stream: stream,
+            queue,
+            max_queued: (config.sample_rate.0 * MAX_LATENCY_MS / 1000) as usize,
+            resampler: Resampler::new(apu::SAMPLE_RATE, config.sample_rate.0),
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Resamples and queues the given APU samples to be played.
+    pub fn play(&mut self, samples: impl IntoIterator<Item = StereoSample>) {
+        self.resampler.process(samples, &mut self.buffer);
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(self.buffer.drain(..));
+        let excess = queue.len().saturating_sub(self.max_queued);
+        queue.drain(..excess);
+    }
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    queue: Arc<Mutex<VecDeque<StereoSample>>>,
+) -> Result<Stream, cpal::BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let mut queue = queue.lock().unwrap();
+            for frame in data.chunks_mut(channels) {
+                // Play silence if emulation falls behind.
+                let sample = queue.pop_front().unwrap_or_default();
+                write_frame(frame, sample);
+            }
+        },
+        |error| log::error!("Audio playback error: {error}"),
+        None,
+    )
+}
+
+// Writes one sample to every channel of an output frame. Mono devices get the
+// average of both sides, and any channels beyond the first 2 are left silent.
+fn write_frame<T: Sample + FromSample<f32>>(frame: &mut [T], sample: StereoSample) {
+    match frame {
+        [mono] => *mono = T::from_sample((sample.left + sample.right) / 2.0),
+        [left, right, rest @ ..] => {
+            *left = T::from_sample(sample.left);
+            *right = T::from_sample(sample.right);
+            for channel in rest {
+                *channel = T::from_sample(0.0);
+            }
+        },
+        [] => {},
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frames_are_written_for_any_channel_count() {
+        let sample = StereoSample { left: 1.0, right: -0.5 };
+
+        let mut mono = [0.0f32];
+        write_frame(&mut mono, sample);
+        assert_eq!(mono, [0.25]);
+
+        let mut surround = [1i16; 4];
+        write_frame(&mut surround, sample);
+        assert_eq!(surround, [i16::MAX, -16384, 0, 0]);
+    }
+}
@@ -0,0 +1,471 @@
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// One shade (0-3, where 0 is the lightest) per pixel, row by row.
+pub type Framebuffer = [u8; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+pub const VRAM_START: u16 = 0x8000;
+pub const VRAM_END: u16 = 0x9FFF;
+pub const OAM_START: u16 = 0xFE00;
+pub const OAM_END: u16 = 0xFE9F;
+
+pub const LCDC: u16 = 0xFF40;
+pub const STAT: u16 = 0xFF41;
+pub const SCY: u16 = 0xFF42;
+pub const SCX: u16 = 0xFF43;
+pub const LY: u16 = 0xFF44;
+pub const LYC: u16 = 0xFF45;
+pub const BGP: u16 = 0xFF47;
+pub const OBP0: u16 = 0xFF48;
+pub const OBP1: u16 = 0xFF49;
+pub const WY: u16 = 0xFF4A;
+pub const WX: u16 = 0xFF4B;
+
+pub const INTERRUPT_VBLANK: u8 = 1 << 0;
+pub const INTERRUPT_STAT: u8 = 1 << 1;
+
+const DOTS_PER_LINE: u16 = 456;
+const LINES_PER_FRAME: u8 = 154;
+const OAM_SCAN_DOTS: u16 = 80;
+// Mode 3 actually takes a variable amount of time depending on scrolling,
+// the window and sprites. This is the minimum.
+const PIXEL_TRANSFER_DOTS: u16 = 172;
+
+const MAX_SPRITES_PER_LINE: usize = 10;
+
+const LCDC_BG_ENABLE: u8 = 1 << 0;
+const LCDC_OBJ_ENABLE: u8 = 1 << 1;
+const LCDC_OBJ_SIZE: u8 = 1 << 2;
+const LCDC_BG_TILE_MAP: u8 = 1 << 3;
+const LCDC_TILE_DATA: u8 = 1 << 4;
+const LCDC_WINDOW_ENABLE: u8 = 1 << 5;
+const LCDC_WINDOW_TILE_MAP: u8 = 1 << 6;
+const LCDC_LCD_ENABLE: u8 = 1 << 7;
+
+const STAT_COINCIDENCE: u8 = 1 << 2;
+const STAT_HBLANK_INTERRUPT: u8 = 1 << 3;
+const STAT_VBLANK_INTERRUPT: u8 = 1 << 4;
+const STAT_OAM_INTERRUPT: u8 = 1 << 5;
+const STAT_COINCIDENCE_INTERRUPT: u8 = 1 << 6;
+
+const SPRITE_PALETTE: u8 = 1 << 4;
+const SPRITE_X_FLIP: u8 = 1 << 5;
+const SPRITE_Y_FLIP: u8 = 1 << 6;
+const SPRITE_BEHIND_BG: u8 = 1 << 7;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    PixelTransfer = 3,
+}
+
+/// The Pixel Processing Unit, which owns VRAM, OAM and the LCD registers
+/// (0xFF40-0xFF4B, except for DMA which is handled by the system).
+///
+/// Scanlines are rendered all at once at the start of HBlank rather than pixel
+/// by pixel, so mid-scanline register writes are not reflected.
+pub struct Ppu {
+    vram: [u8; 0x2000],
+    oam: [u8; 0xA0],
+
+    lcdc: u8,
+    stat: u8,
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+
+    mode: Mode,
+    dot: u16,
+    // The window has its own line counter, which only advances on lines where the
+    // window was actually drawn.
+    window_line: u8,
+    // STAT interrupts are only requested when the OR of all the enabled sources
+    // goes from low to high.
+    stat_line: bool,
+
+    // The frame being drawn, and the last completed frame.
+    back_buffer: Box<Framebuffer>,
+    front_buffer: Box<Framebuffer>,
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Self {
+            vram: [0; 0x2000],
+            oam: [0; 0xA0],
+            lcdc: 0,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            mode: Mode::HBlank,
+            dot: 0,
+            window_line: 0,
+            stat_line: false,
+            back_buffer: Box::new([0; SCREEN_WIDTH * SCREEN_HEIGHT]),
+            front_buffer: Box::new([0; SCREEN_WIDTH * SCREEN_HEIGHT]),
+        }
+    }
+
+    /// The last completed frame.
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.front_buffer
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            VRAM_START..=VRAM_END => self.vram[(address - VRAM_START) as usize],
+            OAM_START..=OAM_END => self.oam[(address - OAM_START) as usize],
+            LCDC => self.lcdc,
+            // Bit 7 is unused and always reads as 1.
+            STAT => 0x80 | self.stat | self.mode as u8,
+            SCY => self.scy,
+            SCX => self.scx,
+            LY => self.ly,
+            LYC => self.lyc,
+            BGP => self.bgp,
+            OBP0 => self.obp0,
+            OBP1 => self.obp1,
+            WY => self.wy,
+            WX => self.wx,
+            _ => unreachable!("address {:#06X} is not mapped to the PPU", address),
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            VRAM_START..=VRAM_END => self.vram[(address - VRAM_START) as usize] = value,
+            OAM_START..=OAM_END => self.oam[(address - OAM_START) as usize] = value,
+            LCDC => {
+                let was_enabled = self.lcdc & LCDC_LCD_ENABLE != 0;
+                self.lcdc = value;
+                if was_enabled && value & LCDC_LCD_ENABLE == 0 {
+                    self.ly = 0;
+                    self.dot = 0;
+                    self.window_line = 0;
+                    self.mode = Mode::HBlank;
+                    self.front_buffer.fill(0);
+                }
+            },
+            // The mode and coincidence bits are read-only.
+            STAT => self.stat = (value & 0x78) | (self.stat & STAT_COINCIDENCE),
+            SCY => self.scy = value,
+            SCX => self.scx = value,
+            LY => {},
+            LYC => self.lyc = value,
+            BGP => self.bgp = value,
+            OBP0 => self.obp0 = value,
+            OBP1 => self.obp1 = value,
+            WY => self.wy = value,
+            WX => self.wx = value,
+            _ => unreachable!("address {:#06X} is not mapped to the PPU", address),
+        }
+    }
+
+    /// Advances the PPU by a single M-cycle (4 dots) and returns the
+    /// interrupts that should be requested, as bits of IF.
+    pub fn tick(&mut self) -> u8 {
+        if self.lcdc & LCDC_LCD_ENABLE == 0 {
+            return 0;
+        }
+
+        let mut interrupts = 0;
+        for _ in 0..4 {
+            self.dot += 1;
+            if self.dot == DOTS_PER_LINE {
+                self.dot = 0;
+                self.ly = (self.ly + 1) % LINES_PER_FRAME;
+            }
+
+            let mode = match (self.ly, self.dot) {
+                (ly, _) if ly as usize >= SCREEN_HEIGHT => Mode::VBlank,
+                (_, dot) if dot < OAM_SCAN_DOTS => Mode::OamScan,
+                (_, dot) if dot < OAM_SCAN_DOTS + PIXEL_TRANSFER_DOTS => Mode::PixelTransfer,
+                _ => Mode::HBlank,
+            };
+            if mode != self.mode {
+                self.mode = mode;
+                match mode {
+                    Mode::HBlank => self.render_scanline(),
+                    Mode::VBlank => {
+                        std::mem::swap(&mut self.front_buffer, &mut self.back_buffer);
+                        self.window_line = 0;
+                        interrupts |= INTERRUPT_VBLANK;
+                    },
+                    Mode::OamScan | Mode::PixelTransfer => {},
+                }
+            }
+
+            if self.update_stat() {
+                interrupts |= INTERRUPT_STAT;
+            }
+        }
+        interrupts
+    }
+
+    // Updates the coincidence flag and returns true if a STAT interrupt should be
+    // requested.
+    fn update_stat(&mut self) -> bool {
+        if self.ly == self.lyc {
+            self.stat |= STAT_COINCIDENCE;
+        } else {
+            self.stat &= !STAT_COINCIDENCE;
+        }
+
+        let line = (self.stat & STAT_COINCIDENCE_INTERRUPT != 0 && self.ly == self.lyc)
+            || (self.stat & STAT_HBLANK_INTERRUPT != 0 && self.mode == Mode::HBlank)
+            || (self.stat & STAT_VBLANK_INTERRUPT != 0 && self.mode == Mode::VBlank)
+            || (self.stat & STAT_OAM_INTERRUPT != 0 && self.mode == Mode::OamScan);
+        let rising = line && !self.stat_line;
+        self.stat_line = line;
+        rising
+    }
+
+    fn render_scanline(&mut self) {
+        let ly = self.ly;
+        // The raw color index of the background/window at each pixel, before the
+        // palette is applied. Sprites use this to decide whether they are hidden.
+        let mut bg_colors = [0u8; SCREEN_WIDTH];
+
+        if self.lcdc & LCDC_BG_ENABLE != 0 {
+            let map = if self.lcdc & LCDC_BG_TILE_MAP != 0 { 0x9C00 } else { 0x9800 };
+            let y = ly.wrapping_add(self.scy);
+            for (x, color) in bg_colors.iter_mut().enumerate() {
+                *color = self.tile_map_color(map, (x as u8).wrapping_add(self.scx), y);
+            }
+
+            // The window can only be enabled on the DMG if the background is too.
+            let window_visible =
+                self.lcdc & LCDC_WINDOW_ENABLE != 0 && ly >= self.wy && self.wx <= 166;
+            if window_visible {
+                let map = if self.lcdc & LCDC_WINDOW_TILE_MAP != 0 { 0x9C00 } else { 0x9800 };
+                // WX is offset by 7, so the window starts at screen x = WX - 7.
+                let start = self.wx.saturating_sub(7) as usize;
+                let offset = 7u8.saturating_sub(self.wx);
+                for (x, color) in bg_colors.iter_mut().enumerate().skip(start) {
+                    let window_x = (x - start) as u8 + offset;
+                    *color = self.tile_map_color(map, window_x, self.window_line);
+                }
+                self.window_line += 1;
+            }
+        }
+
+        let row = ly as usize * SCREEN_WIDTH;
+        for (x, color) in bg_colors.iter().enumerate() {
+            self.back_buffer[row + x] = apply_palette(self.bgp, *color);
+        }
+
+        if self.lcdc & LCDC_OBJ_ENABLE != 0 {
+            self.render_sprites(&bg_colors);
+        }
+    }
+
+    fn render_sprites(&mut self, bg_colors: &[u8; SCREEN_WIDTH]) {
+        let ly = self.ly as i16;
+        let height = if self.lcdc & LCDC_OBJ_SIZE != 0 { 16 } else { 8 };
+
+        // Only the first 10 sprites in OAM that overlap this line are drawn.
+        let mut sprites: Vec<_> = self
+            .oam
+            .chunks_exact(4)
+            .filter(|sprite| {
+                let top = sprite[0] as i16 - 16;
+                ly >= top && ly < top + height
+            })
+            .take(MAX_SPRITES_PER_LINE)
+            .collect();
+
+        // When sprites overlap, the one with the smaller X coordinate wins, then the
+        // one that comes first in OAM (the sort is stable).
+        sprites.sort_by_key(|sprite| sprite[1]);
+
+        let row = self.ly as usize * SCREEN_WIDTH;
+        for (screen_x, bg_color) in bg_colors.iter().enumerate() {
+            for sprite in &sprites {
+                let (y, x, tile, flags) =
+                    (sprite[0] as i16, sprite[1] as i16, sprite[2], sprite[3]);
+                let pixel = screen_x as i16 - (x - 8);
+                if !(0..8).contains(&pixel) {
+                    continue;
+                }
+
+                let mut line = (ly - (y - 16)) as u8;
+                if flags & SPRITE_Y_FLIP != 0 {
+                    line = height as u8 - 1 - line;
+                }
+                // In 8x16 mode, bit 0 of the tile index is ignored.
+                let tile = if height == 16 { tile & 0xFE } else { tile };
+                let address = 0x8000 + tile as u16 * 16 + line as u16 * 2;
+                let bit = if flags & SPRITE_X_FLIP != 0 { pixel } else { 7 - pixel } as u8;
+
+                // Color 0 is transparent, so the next sprite gets a chance to draw.
+                let color = self.tile_row_color(address, bit);
+                if color == 0 {
+                    continue;
+                }
+
+                // The winning sprite can still be hidden behind the background, which
+                // does not let any other sprite through.
+                if flags & SPRITE_BEHIND_BG == 0 || *bg_color == 0 {
+                    let palette = if flags & SPRITE_PALETTE != 0 { self.obp1 } else { self.obp0 };
+                    self.back_buffer[row + screen_x] = apply_palette(palette, color);
+                }
+                break;
+            }
+        }
+    }
+
+    // Returns the color index of the pixel at (x, y) in the 256x256 area described
+    // by the tile map at `map`.
+    fn tile_map_color(&self, map: u16, x: u8, y: u8) -> u8 {
+        let tile = self.vram_byte(map + (y as u16 / 8) * 32 + x as u16 / 8);
+        let address = if self.lcdc & LCDC_TILE_DATA != 0 {
+            0x8000 + tile as u16 * 16
+        } else {
+            // Tiles are addressed with a signed index relative to 0x9000.
+            (0x9000i32 + (tile as i8) as i32 * 16) as u16
+        };
+        self.tile_row_color(address + (y as u16 % 8) * 2, 7 - x % 8)
+    }
+
+    // Each row of a tile is 2 bytes, where the first holds the low bit of each
+    // pixel's color and the second holds the high bit.
+    fn tile_row_color(&self, address: u16, bit: u8) -> u8 {
+        let low = (self.vram_byte(address) >> bit) & 0x01;
+        let high = (self.vram_byte(address + 1) >> bit) & 0x01;
+        (high << 1) | low
+    }
+
+    fn vram_byte(&self, address: u16) -> u8 {
+        self.vram[(address - VRAM_START) as usize]
+    }
+}
+
+fn apply_palette(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0x03
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const M_CYCLES_PER_LINE: usize = DOTS_PER_LINE as usize / 4;
+
+    fn enabled_ppu() -> Ppu {
+        let mut ppu = Ppu::new();
+        ppu.write(LCDC, LCDC_LCD_ENABLE | LCDC_BG_ENABLE | LCDC_TILE_DATA);
+        ppu.write(BGP, 0xE4);
+        ppu
+    }
+
+    fn run_frame(ppu: &mut Ppu) -> u8 {
+        let mut interrupts = 0;
+        for _ in 0..M_CYCLES_PER_LINE * LINES_PER_FRAME as usize {
+            interrupts |= ppu.tick();
+        }
+        interrupts
+    }
+
+    #[test]
+    fn ly_advances_every_456_dots_and_wraps() {
+        let mut ppu = enabled_ppu();
+        for _ in 0..M_CYCLES_PER_LINE {
+            ppu.tick();
+        }
+        assert_eq!(ppu.read(LY), 1);
+
+        for _ in 0..M_CYCLES_PER_LINE * 153 {
+            ppu.tick();
+        }
+        assert_eq!(ppu.read(LY), 0);
+    }
+
+    #[test]
+    fn vblank_interrupt_is_requested_once_per_frame() {
+        let mut ppu = enabled_ppu();
+        let mut count = 0;
+        for _ in 0..M_CYCLES_PER_LINE * LINES_PER_FRAME as usize * 2 {
+            if ppu.tick() & INTERRUPT_VBLANK != 0 {
+                count += 1;
+            }
+        }
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn lyc_coincidence_requests_stat_interrupt() {
+        let mut ppu = enabled_ppu();
+        ppu.write(LYC, 10);
+        ppu.write(STAT, STAT_COINCIDENCE_INTERRUPT);
+        let interrupts = run_frame(&mut ppu);
+        assert_eq!(interrupts & INTERRUPT_STAT, INTERRUPT_STAT);
+        assert_eq!(ppu.read(STAT) & STAT_COINCIDENCE_INTERRUPT, STAT_COINCIDENCE_INTERRUPT);
+    }
+
+    #[test]
+    fn background_tiles_are_rendered_through_palette() {
+        let mut ppu = enabled_ppu();
+        // Tile 1 is solid color 3, and it fills the top-left corner of the map.
+        for byte in 0..16 {
+            ppu.write(0x8010 + byte, 0xFF);
+        }
+        ppu.write(0x9800, 1);
+        // Invert the palette so color 0 shows up as shade 3.
+        ppu.write(BGP, 0x1B);
+
+        run_frame(&mut ppu);
+        let framebuffer = ppu.framebuffer();
+        assert_eq!(framebuffer[0], 0);
+        assert_eq!(framebuffer[7 * SCREEN_WIDTH + 7], 0);
+        assert_eq!(framebuffer[8], 3);
+        assert_eq!(framebuffer[8 * SCREEN_WIDTH], 3);
+    }
+
+    #[test]
+    fn sprites_are_drawn_over_background_with_transparency() {
+        let mut ppu = enabled_ppu();
+        ppu.write(LCDC, ppu.read(LCDC) | LCDC_OBJ_ENABLE);
+        ppu.write(OBP0, 0xE4);
+        // Tile 2 has only its leftmost column set to color 1.
+        for row in 0..8 {
+            ppu.write(0x8020 + row * 2, 0x80);
+        }
+        // Sprite 0 at screen (20, 30) using tile 2.
+        ppu.write(OAM_START, 30 + 16);
+        ppu.write(OAM_START + 1, 20 + 8);
+        ppu.write(OAM_START + 2, 2);
+
+        run_frame(&mut ppu);
+        let framebuffer = ppu.framebuffer();
+        assert_eq!(framebuffer[30 * SCREEN_WIDTH + 20], 1);
+        assert_eq!(framebuffer[30 * SCREEN_WIDTH + 21], 0);
+        assert_eq!(framebuffer[29 * SCREEN_WIDTH + 20], 0);
+    }
+
+    #[test]
+    fn disabling_lcd_resets_ly_and_blanks_screen() {
+        let mut ppu = enabled_ppu();
+        ppu.write(BGP, 0xFF);
+        run_frame(&mut ppu);
+        assert_eq!(ppu.framebuffer()[0], 3);
+
+        ppu.write(LCDC, 0);
+        assert_eq!(ppu.read(LY), 0);
+        assert_eq!(ppu.read(STAT) & 0x03, 0);
+        assert_eq!(ppu.framebuffer()[0], 0);
+    }
+}
@@ -0,0 +1,70 @@
+use crate::apu::StereoSample;
+
+/// Converts a stream of samples from one sample rate to another using linear
+/// interpolation.
+///
+/// This is only meant to bring the APU output down to a standard rate. The APU
+/// already averages its output, so there is no extra filtering done here.
+pub struct Resampler {
+    // How far the input advances (in input samples) for every output sample.
+    step: f64,
+    // The position of the next output sample, relative to `previous`.
+    position: f64,
+    previous: StereoSample,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        Self {
+            step: input_rate as f64 / output_rate as f64,
+            position: 0.0,
+            previous: StereoSample::default(),
+        }
+    }
+
+    pub fn process(
+        &mut self,
+        input: impl IntoIterator<Item = StereoSample>,
+        output: &mut Vec<StereoSample>,
+    ) {
+        for current in input {
+            while self.position < 1.0 {
+                let t = self.position as f32;
+                output.push(StereoSample {
+                    left: self.previous.left + (current.left - self.previous.left) * t,
+                    right: self.previous.right + (current.right - self.previous.right) * t,
+                });
+                self.position += self.step;
+            }
+            self.position -= 1.0;
+            self.previous = current;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn output_length_matches_output_rate() {
+        let mut resampler = Resampler::new(65_536, 44_100);
+        let mut output = Vec::new();
+        resampler.process(vec![StereoSample::default(); 65_536], &mut output);
+        assert_eq!(output.len(), 44_100);
+    }
+
+    #[test]
+    fn interpolates_between_input_samples() {
+        let mut resampler = Resampler::new(1, 4);
+        let mut output = Vec::new();
+        resampler.process(
+            vec![StereoSample { left: 0.0, right: 0.0 }, StereoSample { left: 1.0, right: -1.0 }],
+            &mut output,
+        );
+        let left: Vec<_> = output.iter().map(|sample| sample.left).collect();
+        let right: Vec<_> = output.iter().map(|sample| sample.right).collect();
+        assert_eq!(left, [0.0, 0.0, 0.0, 0.0, 0.0, 0.25, 0.5, 0.75]);
+        assert_eq!(right, [0.0, 0.0, 0.0, 0.0, 0.0, -0.25, -0.5, -0.75]);
+    }
+}
@@ -0,0 +1,223 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::{fs, io};
+
+use crate::ppu::{Framebuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// The colors used for each of the 4 shades, from lightest to darkest.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Palette([[u8; 3]; 4]);
+
+impl Palette {
+    /// The green tint of the original DMG screen.
+    #[rustfmt::skip]
+    pub const DMG: Self = Self([
+        [0x9B, 0xBC, 0x0F],
+        [0x8B, 0xAC, 0x0F],
+        [0x30, 0x62, 0x30],
+        [0x0F, 0x38, 0x0F],
+    ]);
+    #[rustfmt::skip]
+    pub const GRAYSCALE: Self = Self([
+        [0xFF, 0xFF, 0xFF],
+        [0xAA, 0xAA, 0xAA],
+        [0x55, 0x55, 0x55],
+        [0x00, 0x00, 0x00],
+    ]);
+
+    pub fn color(&self, shade: u8) -> [u8; 3] {
+        self.0[shade as usize & 0x03]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::DMG
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    /// Parses either the name of a built-in palette (`dmg` or `grayscale`), or
+    /// 4 comma separated hex colors from lightest to darkest, e.g.
+    /// `#FFFFFF,#AAAAAA,#555555,#000000`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dmg" => return Ok(Self::DMG),
+            "grayscale" => return Ok(Self::GRAYSCALE),
+            _ => {},
+        }
+
+        let colors: Vec<_> = s.split(',').collect();
+        if colors.len() != 4 {
+            return Err("expected dmg, grayscale, or 4 comma separated hex colors".to_owned());
+        }
+
+        let mut palette = [[0; 3]; 4];
+        for (color, hex) in palette.iter_mut().zip(colors) {
+            let hex = hex.trim().trim_start_matches('#');
+            let value = match u32::from_str_radix(hex, 16) {
+                Ok(value) if hex.len() == 6 => value,
+                _ => return Err(format!("invalid color: {hex}")),
+            };
+            *color = [(value >> 16) as u8, (value >> 8) as u8, value as u8];
+        }
+        Ok(Self(palette))
+    }
+}
+
+/// Writes the framebuffer to `path`, as either PNG or PPM depending on the
+/// extension.
+pub fn save(path: impl AsRef<Path>, framebuffer: &Framebuffer, palette: Palette) -> io::Result<()> {
+    let path = path.as_ref();
+    let bytes = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("png") => {
+            encode_png(framebuffer, palette)
+        },
+        Some(extension) if extension.eq_ignore_ascii_case("ppm") => {
+            encode_ppm(framebuffer, palette)
+        },
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "screenshots must have either a .png or .ppm extension",
+            ))
+        },
+    };
+    fs::write(path, bytes)
+}
+
+fn encode_ppm(framebuffer: &Framebuffer, palette: Palette) -> Vec<u8> {
+    let mut bytes = format!("P6\n{SCREEN_WIDTH} {SCREEN_HEIGHT}\n255\n").into_bytes();
+    for shade in framebuffer.iter() {
+        bytes.extend(palette.color(*shade));
+    }
+    bytes
+}
+
+// To avoid pulling in a compression library, the image data is stored in
+// uncompressed deflate blocks. The screen is small enough for this not to
+// matter.
+fn encode_png(framebuffer: &Framebuffer, palette: Palette) -> Vec<u8> {
+    const MAX_STORED_BLOCK_LEN: usize = 0xFFFF;
+
+    // Every row starts with the filter type, which is always 0 (none).
+    let mut raw = Vec::with_capacity(SCREEN_HEIGHT * (1 + SCREEN_WIDTH * 3));
+    for row in framebuffer.chunks_exact(SCREEN_WIDTH) {
+        raw.push(0);
+        for shade in row {
+            raw.extend(palette.color(*shade));
+        }
+    }
+
+    // zlib header for deflate with a 32K window and no preset dictionary.
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(MAX_STORED_BLOCK_LEN).peekable();
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        zlib.push(is_final as u8);
+        zlib.extend((block.len() as u16).to_le_bytes());
+        zlib.extend((!(block.len() as u16)).to_le_bytes());
+        zlib.extend(block);
+    }
+    zlib.extend(adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend((SCREEN_WIDTH as u32).to_be_bytes());
+    header.extend((SCREEN_HEIGHT as u32).to_be_bytes());
+    // 8 bits per channel, truecolor, deflate, no filtering, not interlaced.
+    header.extend([8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1A\n".to_vec();
+    write_png_chunk(&mut png, b"IHDR", &header);
+    write_png_chunk(&mut png, b"IDAT", &zlib);
+    write_png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % MOD_ADLER;
+        b = (b + a) % MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryInto;
+
+    use super::*;
+
+    fn test_framebuffer() -> Box<Framebuffer> {
+        let mut framebuffer = Box::new([0; SCREEN_WIDTH * SCREEN_HEIGHT]);
+        framebuffer[1] = 3;
+        framebuffer
+    }
+
+    #[test]
+    fn parse_palette() {
+        assert_eq!("dmg".parse(), Ok(Palette::DMG));
+        assert_eq!("#FFFFFF,#aaaaaa,555555, #000000".parse(), Ok(Palette::GRAYSCALE));
+        assert!("#FFFFFF,#AAAAAA,#555555".parse::<Palette>().is_err());
+        assert!("#FFFFFF,#AAAAAA,#555555,#00000".parse::<Palette>().is_err());
+    }
+
+    #[test]
+    fn ppm_has_header_and_rgb_pixels() {
+        let ppm = encode_ppm(&test_framebuffer(), Palette::GRAYSCALE);
+        let header = b"P6\n160 144\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        assert_eq!(ppm.len(), header.len() + SCREEN_WIDTH * SCREEN_HEIGHT * 3);
+        assert_eq!(&ppm[header.len()..header.len() + 6], &[0xFF, 0xFF, 0xFF, 0, 0, 0]);
+    }
+
+    #[test]
+    fn png_chunks_are_well_formed() {
+        let png = encode_png(&test_framebuffer(), Palette::GRAYSCALE);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1A\n");
+
+        let mut offset = 8;
+        let mut kinds = Vec::new();
+        while offset < png.len() {
+            let len = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+            let chunk = &png[offset + 4..offset + 8 + len];
+            let crc =
+                u32::from_be_bytes(png[offset + 8 + len..offset + 12 + len].try_into().unwrap());
+            assert_eq!(crc32(chunk), crc);
+            kinds.push(chunk[..4].to_vec());
+            offset += 12 + len;
+        }
+        assert_eq!(kinds, [b"IHDR".to_vec(), b"IDAT".to_vec(), b"IEND".to_vec()]);
+    }
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+}
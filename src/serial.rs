@@ -0,0 +1,253 @@
+use std::io::{self, Write};
+
+pub const SB: u16 = 0xFF01;
+pub const SC: u16 = 0xFF02;
+
+const SC_TRANSFER_ENABLE: u8 = 0x80;
+const SC_INTERNAL_CLOCK: u8 = 0x01;
+
+/// The device on the other end of the serial port.
+pub trait SerialLink {
+    /// Sends a byte to the other end and returns the byte that was shifted in
+    /// from it during the same transfer.
+    ///
+    /// This is called when the Gameboy starts a transfer using its internal
+    /// clock.
+    fn exchange(&mut self, data: u8) -> u8;
+
+    /// Checks whether the other end has started a transfer using its own
+    /// clock. If it has, `data` is sent back to it and the byte it sent is
+    /// returned.
+    ///
+    /// This is called on every tick of the serial clock while the Gameboy is
+    /// not driving a transfer itself. Only devices that can provide a clock
+    /// need to implement it.
+    fn poll_external(&mut self, _data: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// Nothing is plugged into the serial port, so every bit shifted in is 1.
+pub struct Disconnected;
+
+impl SerialLink for Disconnected {
+    fn exchange(&mut self, _data: u8) -> u8 {
+        0xFF
+    }
+}
+
+/// Writes every byte sent by the Gameboy to the given writer.
+///
+/// Test ROMs (Blargg's in particular) report their results this way.
+pub struct Capture<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> Capture<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl Capture<io::Stdout> {
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl<W: Write> SerialLink for Capture<W> {
+    fn exchange(&mut self, data: u8) -> u8 {
+        if let Err(error) = self.writer.write_all(&[data]).and_then(|_| self.writer.flush()) {
+            log::warn!("Failed to write captured serial data: {error}");
+        }
+        0xFF
+    }
+}
+
+/// The serial port, mapped to SB (0xFF01) and SC (0xFF02).
+pub struct Serial {
+    sb: u8,
+    sc: u8,
+    // The byte being shifted into SB, one bit per serial clock.
+    incoming: u8,
+    bits_remaining: u8,
+    link: Box<dyn SerialLink>,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Self { sb: 0, sc: 0, incoming: 0, bits_remaining: 0, link: Box::new(Disconnected) }
+    }
+
+    pub fn set_link(&mut self, link: Box<dyn SerialLink>) {
+        self.link = link;
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            SB => self.sb,
+            // Only bits 0 and 7 are used on the DMG.
+            SC => self.sc | 0x7E,
+            _ => unreachable!("address {:#06X} is not mapped to the serial port", address),
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            SB => self.sb = value,
+            SC => {
+                self.sc = value;
+                let internal_clock = SC_TRANSFER_ENABLE | SC_INTERNAL_CLOCK;
+                if value & internal_clock == internal_clock {
+                    self.incoming = self.link.exchange(self.sb);
+                    self.bits_remaining = 8;
+                }
+            },
+            _ => unreachable!("address {:#06X} is not mapped to the serial port", address),
+        }
+    }
+
+    /// Shifts a single bit in and out of SB, if a transfer is in progress.
+    /// Otherwise, checks whether the other end has clocked a transfer of its
+    /// own.
+    ///
+    /// This is called by the system on every falling edge of the serial clock
+    /// (8192 Hz) and returns true when a transfer completes, meaning the serial
+    /// interrupt should be requested.
+    pub fn clock(&mut self) -> bool {
+        if self.bits_remaining == 0 {
+            return self.poll_external();
+        }
+
+        self.sb = (self.sb << 1) | (self.incoming >> 7);
+        self.incoming <<= 1;
+        self.bits_remaining -= 1;
+
+        if self.bits_remaining == 0 {
+            self.sc &= !SC_TRANSFER_ENABLE;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn poll_external(&mut self) -> bool {
+        // When the other end provides the clock, all 8 bits arrive at once rather
+        // than being shifted in one at a time. If this end hasn't started a
+        // transfer, the other end reads all 1s.
+        let ready = self.sc & (SC_TRANSFER_ENABLE | SC_INTERNAL_CLOCK) == SC_TRANSFER_ENABLE;
+        match self.link.poll_external(if ready { self.sb } else { 0xFF }) {
+            Some(data) if ready => {
+                self.sb = data;
+                self.sc &= !SC_TRANSFER_ENABLE;
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    fn transfer(serial: &mut Serial, data: u8) {
+        serial.write(SB, data);
+        serial.write(SC, SC_TRANSFER_ENABLE | SC_INTERNAL_CLOCK);
+        for _ in 0..7 {
+            assert!(!serial.clock());
+        }
+        assert!(serial.clock());
+    }
+
+    #[test]
+    fn disconnected_shifts_in_ones() {
+        let mut serial = Serial::new();
+        transfer(&mut serial, 0x42);
+        assert_eq!(serial.read(SB), 0xFF);
+        assert_eq!(serial.read(SC), 0x7F);
+    }
+
+    #[test]
+    fn transfer_shifts_one_bit_per_clock() {
+        let mut serial = Serial::new();
+        serial.write(SB, 0x00);
+        serial.write(SC, SC_TRANSFER_ENABLE | SC_INTERNAL_CLOCK);
+        assert_eq!(serial.read(SC), 0xFF);
+
+        serial.clock();
+        assert_eq!(serial.read(SB), 0x01);
+        serial.clock();
+        assert_eq!(serial.read(SB), 0x03);
+    }
+
+    #[test]
+    fn disconnected_external_clock_never_completes() {
+        let mut serial = Serial::new();
+        serial.write(SB, 0x42);
+        serial.write(SC, SC_TRANSFER_ENABLE);
+        assert!(!serial.clock());
+        assert_eq!(serial.read(SB), 0x42);
+    }
+
+    struct SharedCapture(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedCapture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn capture_records_sent_bytes() {
+        let captured = Rc::default();
+        let mut serial = Serial::new();
+        serial.set_link(Box::new(Capture::new(SharedCapture(Rc::clone(&captured)))));
+        for byte in b"Passed" {
+            transfer(&mut serial, *byte);
+        }
+        assert_eq!(captured.borrow().as_slice(), b"Passed");
+    }
+
+    struct ExternalClock(Option<u8>);
+
+    impl SerialLink for ExternalClock {
+        fn exchange(&mut self, _data: u8) -> u8 {
+            0xFF
+        }
+
+        fn poll_external(&mut self, _data: u8) -> Option<u8> {
+            self.0.take()
+        }
+    }
+
+    #[test]
+    fn external_clock_transfer_completes_when_other_end_clocks() {
+        let mut serial = Serial::new();
+        serial.set_link(Box::new(ExternalClock(None)));
+        serial.write(SB, 0x42);
+        serial.write(SC, SC_TRANSFER_ENABLE);
+        assert!(!serial.clock());
+
+        serial.set_link(Box::new(ExternalClock(Some(0x99))));
+        assert!(serial.clock());
+        assert_eq!(serial.read(SB), 0x99);
+        assert_eq!(serial.read(SC), 0x7E);
+    }
+
+    #[test]
+    fn external_clock_is_ignored_without_transfer_enabled() {
+        let mut serial = Serial::new();
+        serial.set_link(Box::new(ExternalClock(Some(0x99))));
+        serial.write(SB, 0x42);
+        assert!(!serial.clock());
+        assert_eq!(serial.read(SB), 0x42);
+    }
+}
@@ -0,0 +1,357 @@
+use std::fmt::Write as _;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use clap::ValueEnum;
+use console::{Key, Term};
+
+use crate::error::EmulationError;
+use crate::gb::Gameboy;
+use crate::joypad::Button;
+use crate::pacing::{FramePacer, Speed};
+use crate::ppu::{Framebuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::screenshot::Palette;
+use crate::wav::WavRecorder;
+
+// Terminals only report key presses, never releases, so a button is held for
+// this many frames after its key was last seen. Holding a key down relies on
+// the terminal's key repeat to keep the button held.
+const HOLD_FRAMES: u8 = 8;
+
+// The upper half of the cell is drawn with the foreground color and the lower
+// half with the background color, so each cell is 2 pixels tall.
+const UPPER_HALF_BLOCK: char = '▀';
+
+// How long the key reader waits for a key before checking if it should stop.
+#[cfg(unix)]
+const KEY_POLL_MS: i32 = 20;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum ColorMode {
+    /// 24-bit colors.
+    Truecolor,
+    /// The 256 color xterm palette.
+    #[value(name = "256")]
+    Ansi256,
+}
+
+impl ColorMode {
+    /// Uses truecolor if the terminal advertises support for it through
+    /// COLORTERM, which is the convention most terminals follow.
+    pub fn detect() -> Self {
+        match std::env::var("COLORTERM").as_deref() {
+            Ok("truecolor") | Ok("24bit") => Self::Truecolor,
+            _ => Self::Ansi256,
+        }
+    }
+
+    fn foreground(self, output: &mut String, [r, g, b]: [u8; 3]) {
+        let _ = match self {
+            Self::Truecolor => write!(output, "\x1B[38;2;{r};{g};{b}m"),
+            Self::Ansi256 => write!(output, "\x1B[38;5;{}m", ansi256([r, g, b])),
+        };
+    }
+
+    fn background(self, output: &mut String, [r, g, b]: [u8; 3]) {
+        let _ = match self {
+            Self::Truecolor => write!(output, "\x1B[48;2;{r};{g};{b}m"),
+            Self::Ansi256 => write!(output, "\x1B[48;5;{}m", ansi256([r, g, b])),
+        };
+    }
+}
+
+/// Runs the emulator, drawing the LCD in the terminal and reading joypad
+/// input from the keyboard, until `q` or Ctrl+C is pressed or emulation fails.
+///
+/// The screen takes up 160 columns and 72 rows, with the speed shown below
+/// it. The controls are the arrow keys for the D-pad, `x` for A, `z` for B,
+/// Enter for Start and Backspace for Select, and `-` and `+` change the speed.
+pub fn run(
+    mut gameboy: Gameboy,
+    palette: Palette,
+    color_mode: ColorMode,
+    speed: Speed,
+    mut audio_out: Option<WavRecorder>,
+) -> Result<(), EmulationError> {
+    let mut pacer = FramePacer::new(speed);
+    let term = Term::stdout();
+    // These are dropped in reverse order, so the key reader has stopped before the
+    // terminal is restored.
+    let _guard = TerminalGuard::enter(term.clone())?;
+    let keys = KeyReader::spawn();
+    let mut held = [0u8; Button::ALL.len()];
+
+    loop {
+        while let Ok(key) = keys.keys.try_recv() {
+            match key {
+                Key::Char('q') | Key::CtrlC => return Ok(()),
+                Key::Char('-') => pacer.set_speed(pacer.speed().slower()),
+                Key::Char('+') | Key::Char('=') => pacer.set_speed(pacer.speed().faster()),
+                key => {
+                    if let Some(button) = button_for_key(&key) {
+                        held[button as usize] = HOLD_FRAMES;
+                    }
+                },
+            }
+        }
+        for (button, frames) in Button::ALL.iter().zip(held.iter_mut()) {
+            gameboy.set_button(*button, *frames > 0);
+            *frames = frames.saturating_sub(1);
+        }
+
+        gameboy.run_frame()?;
+        gameboy.output_audio(&mut audio_out);
+        if pacer.next_frame() {
+            let mut output = render(gameboy.framebuffer(), palette, color_mode);
+            // Clear to the end of the line, since the previous speed may have been longer.
+            let _ = write!(output, "Speed: {}\x1B[K", pacer.speed());
+            let _ = term.write_str(&output);
+        }
+    }
+}
+
+// Sets the terminal up for drawing the screen, and puts it back the way it was
+// found when dropped, which happens however `run` ends, including by panicking.
+struct TerminalGuard {
+    term: Term,
+    #[cfg(unix)]
+    original: Option<libc::termios>,
+}
+
+impl TerminalGuard {
+    // Switches to the alternate screen and hides the cursor. On Unix, the
+    // terminal is also put in raw mode, so keys can be read as soon as they are
+    // pressed without waiting for Enter and without being echoed.
+    fn enter(term: Term) -> io::Result<Self> {
+        // When stdin isn't a terminal there is nothing to put in raw mode.
+        #[cfg(unix)]
+        let original = match stdin_termios() {
+            Some(original) => {
+                let mut raw = original;
+                // SAFETY: cfmakeraw only changes the flags of the termios it is given.
+                unsafe { libc::cfmakeraw(&mut raw) };
+                // Output is left alone, the same way console does for Term::read_key.
+                raw.c_oflag = original.c_oflag;
+                set_stdin_termios(&raw)?;
+                Some(original)
+            },
+            None => None,
+        };
+        let _ = term.write_str("\x1B[?1049h\x1B[?25l");
+        Ok(Self {
+            term,
+            #[cfg(unix)]
+            original,
+        })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = self.term.write_str("\x1B[0m\x1B[?25h\x1B[?1049l");
+        #[cfg(unix)]
+        if let Some(original) = &self.original {
+            if let Err(error) = set_stdin_termios(original) {
+                log::warn!("Failed to restore the terminal settings: {error}");
+            }
+        }
+    }
+}
+
+// The settings of the terminal on stdin, or None if stdin isn't a terminal.
+#[cfg(unix)]
+fn stdin_termios() -> Option<libc::termios> {
+    let mut termios = std::mem::MaybeUninit::uninit();
+    // SAFETY: tcgetattr only writes to the termios it is given, and fills all of
+    // it in when it succeeds, so it is initialized when assumed to be.
+    unsafe {
+        (libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) == 0)
+            .then(|| termios.assume_init())
+    }
+}
+
+#[cfg(unix)]
+fn set_stdin_termios(termios: &libc::termios) -> io::Result<()> {
+    // SAFETY: tcsetattr only reads the termios it is given.
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, termios) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+// Reads keys on their own thread, so emulation keeps running while no keys are
+// pressed. The thread is stopped when this is dropped.
+struct KeyReader {
+    keys: Receiver<Key>,
+    stop: Arc<AtomicBool>,
+    // Only joined on Unix, where reading never blocks for long; elsewhere reading
+    // a key can't be given up on, so the thread is left to end with the process.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    thread: Option<JoinHandle<()>>,
+}
+
+impl KeyReader {
+    fn spawn() -> Self {
+        let (sender, keys) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        // Keys are read from stdin once one is waiting, which only works when stdin
+        // is the terminal.
+        // SAFETY: isatty only looks at the file descriptor, and doesn't need it to
+        // be open.
+        #[cfg(unix)]
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+            log::warn!("Not reading keys, since stdin is not a terminal");
+            return Self { keys, stop, thread: None };
+        }
+
+        let thread = thread::spawn({
+            let stop = Arc::clone(&stop);
+            move || {
+                let term = Term::stdout();
+                while !stop.load(Ordering::Relaxed) {
+                    if !key_waiting() {
+                        continue;
+                    }
+                    // The raw version returns Ctrl+C as a key instead of raising SIGINT,
+                    // so it quits the same way `q` does.
+                    let Ok(key) = term.read_key_raw() else { return };
+                    if sender.send(key).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Self { keys, stop, thread: Some(thread) }
+    }
+}
+
+impl Drop for KeyReader {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        #[cfg(unix)]
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Waits a little while for a key to be pressed, and returns whether one was.
+#[cfg(unix)]
+fn key_waiting() -> bool {
+    let mut poll_fd = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+    // SAFETY: poll is given a single valid pollfd, and a count of 1 to match.
+    unsafe { libc::poll(&mut poll_fd, 1, KEY_POLL_MS) > 0 }
+}
+
+#[cfg(not(unix))]
+fn key_waiting() -> bool {
+    true
+}
+
+fn button_for_key(key: &Key) -> Option<Button> {
+    match key {
+        Key::ArrowRight => Some(Button::Right),
+        Key::ArrowLeft => Some(Button::Left),
+        Key::ArrowUp => Some(Button::Up),
+        Key::ArrowDown => Some(Button::Down),
+        Key::Char('x') | Key::Char('X') => Some(Button::A),
+        Key::Char('z') | Key::Char('Z') => Some(Button::B),
+        Key::Enter => Some(Button::Start),
+        Key::Backspace => Some(Button::Select),
+        _ => None,
+    }
+}
+
+// Draws the whole framebuffer starting from the top-left corner of the
+// terminal. Colors are only emitted when they change, which keeps the output
+// for a typical frame small.
+fn render(framebuffer: &Framebuffer, palette: Palette, color_mode: ColorMode) -> String {
+    let mut output = String::from("\x1B[H");
+    for y in (0..SCREEN_HEIGHT).step_by(2) {
+        let mut colors = None;
+        for x in 0..SCREEN_WIDTH {
+            let top = palette.color(framebuffer[y * SCREEN_WIDTH + x]);
+            let bottom = palette.color(framebuffer[(y + 1) * SCREEN_WIDTH + x]);
+            if colors.map(|(fg, _)| fg) != Some(top) {
+                color_mode.foreground(&mut output, top);
+            }
+            if colors.map(|(_, bg)| bg) != Some(bottom) {
+                color_mode.background(&mut output, bottom);
+            }
+            colors = Some((top, bottom));
+            output.push(UPPER_HALF_BLOCK);
+        }
+        output.push_str("\x1B[0m\r\n");
+    }
+    output
+}
+
+// Finds the closest color in the xterm 256 color palette, which is either in
+// the 6x6x6 color cube (16-231) or the grayscale ramp (232-255).
+fn ansi256([r, g, b]: [u8; 3]) -> u8 {
+    const CUBE_LEVELS: [u8; 6] = [0x00, 0x5F, 0x87, 0xAF, 0xD7, 0xFF];
+
+    let cube_index = |value: u8| match value {
+        0..=47 => 0,
+        48..=114 => 1,
+        _ => (value - 35) / 40,
+    };
+    let (ri, gi, bi) = (cube_index(r), cube_index(g), cube_index(b));
+    let cube = [CUBE_LEVELS[ri as usize], CUBE_LEVELS[gi as usize], CUBE_LEVELS[bi as usize]];
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_index = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray_level = 8 + gray_index * 10;
+
+    let distance = |[cr, cg, cb]: [u8; 3]| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, cr) + d(g, cg) + d(b, cb)
+    };
+    if distance([gray_level; 3]) < distance(cube) {
+        232 + gray_index
+    } else {
+        16 + 36 * ri + 6 * gi + bi
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ansi256_matches_exact_palette_colors() {
+        assert_eq!(ansi256([0x00, 0x00, 0x00]), 16);
+        assert_eq!(ansi256([0xFF, 0xFF, 0xFF]), 231);
+        assert_eq!(ansi256([0xFF, 0x00, 0x00]), 196);
+        assert_eq!(ansi256([0x5F, 0x87, 0xAF]), 67);
+        assert_eq!(ansi256([0x80, 0x80, 0x80]), 244);
+    }
+
+    #[test]
+    fn render_uses_half_blocks_and_only_emits_changed_colors() {
+        let mut framebuffer = Box::new([0; SCREEN_WIDTH * SCREEN_HEIGHT]);
+        // The second row of the first cell is black.
+        framebuffer[SCREEN_WIDTH] = 3;
+
+        let output = render(&framebuffer, Palette::GRAYSCALE, ColorMode::Truecolor);
+        let first_row = output.split("\r\n").next().unwrap();
+        let expected_start =
+            "\x1B[H\x1B[38;2;255;255;255m\x1B[48;2;0;0;0m▀\x1B[48;2;255;255;255m▀▀";
+        assert!(first_row.starts_with(expected_start));
+        assert_eq!(first_row.matches(UPPER_HALF_BLOCK).count(), SCREEN_WIDTH);
+        assert_eq!(output.matches("\r\n").count(), SCREEN_HEIGHT / 2);
+    }
+
+    #[test]
+    fn keys_map_to_buttons() {
+        assert_eq!(button_for_key(&Key::ArrowUp), Some(Button::Up));
+        assert_eq!(button_for_key(&Key::Char('x')), Some(Button::A));
+        assert_eq!(button_for_key(&Key::Enter), Some(Button::Start));
+        assert_eq!(button_for_key(&Key::Char('q')), None);
+    }
+}
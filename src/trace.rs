@@ -0,0 +1,352 @@
+//! Instruction traces in the format of [Gameboy Doctor], so boyo can be
+//! compared with other emulators one instruction at a time.
+//!
+//! [Gameboy Doctor]: https://github.com/robert/gameboy-doctor
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+
+use crate::bus::Bus;
+use crate::error::EmulationError;
+use crate::gb::{CpuState, Gameboy};
+
+/// The registers before an instruction executes, and the 4 bytes at PC.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TraceLine {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub pcmem: [u8; 4],
+}
+
+impl TraceLine {
+    /// The current state of `gameboy`, which should be at an instruction
+    /// boundary.
+    pub fn new<B: Bus>(gameboy: &Gameboy<B>) -> Self {
+        let cpu = gameboy.cpu();
+        let mut pcmem = [0; 4];
+        for (offset, byte) in pcmem.iter_mut().enumerate() {
+            *byte = gameboy.read_memory(cpu.pc.wrapping_add(offset as u16));
+        }
+        Self {
+            a: cpu.a,
+            f: cpu.f,
+            b: cpu.b,
+            c: cpu.c,
+            d: cpu.d,
+            e: cpu.e,
+            h: cpu.h,
+            l: cpu.l,
+            sp: cpu.sp,
+            pc: cpu.pc,
+            pcmem,
+        }
+    }
+
+    /// The names of the fields that are different in `other`, e.g.
+    /// `["A", "PC"]`.
+    pub fn differences(&self, other: &Self) -> Vec<&'static str> {
+        [
+            ("A", self.a != other.a),
+            ("F", self.f != other.f),
+            ("B", self.b != other.b),
+            ("C", self.c != other.c),
+            ("D", self.d != other.d),
+            ("E", self.e != other.e),
+            ("H", self.h != other.h),
+            ("L", self.l != other.l),
+            ("SP", self.sp != other.sp),
+            ("PC", self.pc != other.pc),
+            ("PCMEM", self.pcmem != other.pcmem),
+        ]
+        .iter()
+        .filter(|(_, differs)| *differs)
+        .map(|(name, _)| *name)
+        .collect()
+    }
+
+    fn load_registers(&self, cpu: &mut CpuState) {
+        *cpu = CpuState {
+            pc: self.pc,
+            sp: self.sp,
+            a: self.a,
+            b: self.b,
+            c: self.c,
+            d: self.d,
+            e: self.e,
+            f: self.f,
+            h: self.h,
+            l: self.l,
+        };
+    }
+}
+
+impl fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} \
+             PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            self.a,
+            self.f,
+            self.b,
+            self.c,
+            self.d,
+            self.e,
+            self.h,
+            self.l,
+            self.sp,
+            self.pc,
+            self.pcmem[0],
+            self.pcmem[1],
+            self.pcmem[2],
+            self.pcmem[3],
+        )
+    }
+}
+
+impl FromStr for TraceLine {
+    type Err = String;
+
+    /// Parses a line in the Gameboy Doctor format, as written by `Display`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split_whitespace();
+        let mut byte = |name| parse_hex(field(&mut fields, name)?).map(|value| value as u8);
+        let (a, f, b, c, d, e, h, l) = (
+            byte("A")?,
+            byte("F")?,
+            byte("B")?,
+            byte("C")?,
+            byte("D")?,
+            byte("E")?,
+            byte("H")?,
+            byte("L")?,
+        );
+        let sp = parse_hex(field(&mut fields, "SP")?)?;
+        let pc = parse_hex(field(&mut fields, "PC")?)?;
+
+        let mut pcmem = [0; 4];
+        let mut bytes = field(&mut fields, "PCMEM")?.split(',');
+        for byte in &mut pcmem {
+            *byte = parse_hex(bytes.next().ok_or("expected 4 bytes in PCMEM")?)? as u8;
+        }
+        if bytes.next().is_some() || fields.next().is_some() {
+            return Err("unexpected data after PCMEM".to_owned());
+        }
+
+        Ok(Self { a, f, b, c, d, e, h, l, sp, pc, pcmem })
+    }
+}
+
+// The value of the next field, which should be called `name`.
+fn field<'a>(fields: &mut impl Iterator<Item = &'a str>, name: &str) -> Result<&'a str, String> {
+    let field = fields.next().ok_or_else(|| format!("missing {name}"))?;
+    field
+        .strip_prefix(name)
+        .and_then(|value| value.strip_prefix(':'))
+        .ok_or_else(|| format!("expected {name}, found {field}"))
+}
+
+fn parse_hex(hex: &str) -> Result<u16, String> {
+    u16::from_str_radix(hex, 16).map_err(|_| format!("invalid hex value: {hex}"))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TraceDiffError {
+    #[error("failed to read reference trace: {0}")]
+    Io(#[from] io::Error),
+    #[error("line {line} of the reference trace: {message}")]
+    Parse { line: u64, message: String },
+    #[error("{source} (after line {line})")]
+    Emulation { line: u64, source: EmulationError },
+}
+
+pub enum DiffOutcome {
+    /// Every line of the reference matched.
+    Matched {
+        lines: u64,
+    },
+    Mismatch(Mismatch),
+}
+
+/// The first line where boyo differed from the reference trace.
+pub struct Mismatch {
+    /// The line number in the reference, counting from 1.
+    pub line: u64,
+    /// The matching lines before it, oldest first.
+    pub context: Vec<TraceLine>,
+    pub expected: TraceLine,
+    pub actual: TraceLine,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let differences = self.expected.differences(&self.actual);
+        writeln!(f, "mismatch at line {}, {} differs:", self.line, differences.join(", "))?;
+        let first_line = self.line - self.context.len() as u64;
+        for (line, trace) in (first_line..).zip(&self.context) {
+            writeln!(f, "  {line:>10}  {trace}")?;
+        }
+        writeln!(f, "- {:>10}  {}", self.line, self.expected)?;
+        write!(f, "+ {:>10}  {}", self.line, self.actual)
+    }
+}
+
+/// Runs `gameboy` one instruction at a time, comparing its state with each
+/// line of a reference trace in the Gameboy Doctor format, until the first
+/// line that differs. Up to `context` matching lines before it are kept.
+///
+/// The registers start out as they are on the first line of the reference,
+/// since the Gameboy doesn't start with the values that the boot ROM leaves
+/// behind.
+pub fn diff<B: Bus>(
+    gameboy: &mut Gameboy<B>,
+    reference: impl BufRead,
+    context: usize,
+) -> Result<DiffOutcome, TraceDiffError> {
+    let mut recent = VecDeque::with_capacity(context);
+    let mut lines = 0;
+    for text in reference.lines() {
+        let text = text?;
+        let line = lines + 1;
+        let expected: TraceLine =
+            text.parse().map_err(|message| TraceDiffError::Parse { line, message })?;
+
+        if lines == 0 {
+            expected.load_registers(gameboy.cpu_mut());
+        } else {
+            gameboy
+                .step_instruction()
+                .map_err(|source| TraceDiffError::Emulation { line: lines, source })?;
+        }
+
+        let actual = TraceLine::new(gameboy);
+        if actual != expected {
+            return Ok(DiffOutcome::Mismatch(Mismatch {
+                line,
+                context: recent.into(),
+                expected,
+                actual,
+            }));
+        }
+        if context > 0 {
+            if recent.len() == context {
+                recent.pop_front();
+            }
+            recent.push_back(actual);
+        }
+        lines = line;
+    }
+    Ok(DiffOutcome::Matched { lines })
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{CpuState, TestBus, INITIAL_PC};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trace_line_uses_gameboy_doctor_format() {
+        let mut bus = TestBus::new();
+        bus.memory_mut()[0xFFFE..].copy_from_slice(&[0x31, 0xFE]);
+        bus.memory_mut()[..2].copy_from_slice(&[0xFF, 0xC3]);
+        let cpu =
+            CpuState { pc: 0xFFFE, sp: 0xDFF0, a: 0x01, f: 0xB0, l: 0x4D, ..CpuState::default() };
+        let gameboy = Gameboy::from_parts(cpu, bus);
+
+        assert_eq!(
+            TraceLine::new(&gameboy).to_string(),
+            "A:01 F:B0 B:00 C:00 D:00 E:00 H:00 L:4D SP:DFF0 PC:FFFE PCMEM:31,FE,FF,C3"
+        );
+    }
+
+    #[test]
+    fn gameboy_traces_every_instruction_before_executing_it() {
+        // LD B,$12, JP $0100.
+        let mut rom = vec![0; INITIAL_PC as usize];
+        rom.extend([0x06, 0x12, 0xC3, 0x00, 0x01]);
+        let mut gameboy = Gameboy::new(rom).unwrap();
+        let buffer = SharedBuffer::default();
+        gameboy.set_trace(Box::new(buffer.clone()));
+
+        for _ in 0..3 {
+            gameboy.step_instruction().unwrap();
+        }
+        let trace = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        assert_eq!(trace.lines().collect::<Vec<_>>(), [
+            "A:00 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0100 PCMEM:06,12,C3,00",
+            "A:00 F:00 B:12 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0102 PCMEM:C3,00,01,FF",
+            "A:00 F:00 B:12 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0100 PCMEM:06,12,C3,00",
+        ]);
+    }
+
+    #[test]
+    fn parse_trace_line() {
+        let text = "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02";
+        let line: TraceLine = text.parse().unwrap();
+        assert_eq!(line.c, 0x13);
+        assert_eq!(line.pcmem, [0x00, 0xC3, 0x13, 0x02]);
+        assert_eq!(line.to_string(), text);
+
+        assert!("A:01 F:B0".parse::<TraceLine>().is_err());
+        assert!(text.replace("SP", "PC").parse::<TraceLine>().is_err());
+        assert!(text.replace("C3", "XY").parse::<TraceLine>().is_err());
+    }
+
+    #[test]
+    fn diff_stops_at_first_mismatch() {
+        // LD B,$12, LD C,$34, JP $0100.
+        let mut rom = vec![0; INITIAL_PC as usize];
+        rom.extend([0x06, 0x12, 0x0E, 0x34, 0xC3, 0x00, 0x01]);
+        let reference = "\
+A:01 F:B0 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0100 PCMEM:06,12,0E,34
+A:01 F:B0 B:12 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0102 PCMEM:0E,34,C3,00
+A:01 F:B0 B:12 C:34 D:00 E:00 H:00 L:00 SP:FFFE PC:0104 PCMEM:C3,00,01,FF
+A:01 F:B0 B:12 C:34 D:00 E:00 H:00 L:00 SP:FFFE PC:0100 PCMEM:06,12,0E,34
+";
+
+        let mut gameboy = Gameboy::new(rom.clone()).unwrap();
+        let outcome = diff(&mut gameboy, reference.as_bytes(), 2).unwrap();
+        assert!(matches!(outcome, DiffOutcome::Matched { lines: 4 }));
+
+        let reference = reference.replace(
+            "C:34 D:00 E:00 H:00 L:00 SP:FFFE PC:0104",
+            "C:35 D:00 E:00 H:00 L:00 SP:FFFE PC:0104",
+        );
+        let mut gameboy = Gameboy::new(rom).unwrap();
+        let DiffOutcome::Mismatch(mismatch) = diff(&mut gameboy, reference.as_bytes(), 1).unwrap()
+        else {
+            panic!("the traces should differ");
+        };
+        assert_eq!(mismatch.line, 3);
+        assert_eq!(mismatch.context.len(), 1);
+        assert_eq!(mismatch.expected.differences(&mismatch.actual), ["C"]);
+    }
+}
@@ -0,0 +1,159 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::apu::{self, StereoSample};
+use crate::resampler::Resampler;
+
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+const HEADER_LEN: u32 = 44;
+// The RIFF size in the header, which counts everything after it, is 32 bits.
+const MAX_DATA_LEN: u32 = u32::MAX - (HEADER_LEN - 8);
+
+/// Writes 16-bit stereo PCM samples to a WAV file.
+///
+/// The sizes in the header are only correct after [`WavWriter::flush`] has
+/// been called, which can be done periodically so the file stays playable even
+/// if the process is killed.
+///
+/// A WAV file can't be bigger than 4 GiB, so once that much has been written,
+/// [`WavWriter::write_sample`] fails without writing anything.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    sample_rate: u32,
+    data_len: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(writer: W, sample_rate: u32) -> io::Result<Self> {
+        let mut wav = Self { writer, sample_rate, data_len: 0 };
+        wav.write_header()?;
+        Ok(wav)
+    }
+
+    pub fn write_sample(&mut self, sample: StereoSample) -> io::Result<()> {
+        let data_len = self
+            .data_len
+            .checked_add(BLOCK_ALIGN as u32)
+            .filter(|len| *len <= MAX_DATA_LEN)
+            .ok_or_else(|| io::Error::other("WAV files can't be bigger than 4 GiB"))?;
+        self.writer.write_all(&to_pcm(sample.left).to_le_bytes())?;
+        self.writer.write_all(&to_pcm(sample.right).to_le_bytes())?;
+        self.data_len = data_len;
+        Ok(())
+    }
+
+    /// Patches the header with the amount of data written so far and flushes
+    /// the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let byte_rate = self.sample_rate * BLOCK_ALIGN as u32;
+
+        self.writer.write_all(b"RIFF")?;
+        self.writer.write_all(&(HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.writer.write_all(b"WAVE")?;
+
+        self.writer.write_all(b"fmt ")?;
+        self.writer.write_all(&16u32.to_le_bytes())?;
+        // Format 1 is uncompressed PCM.
+        self.writer.write_all(&1u16.to_le_bytes())?;
+        self.writer.write_all(&CHANNELS.to_le_bytes())?;
+        self.writer.write_all(&self.sample_rate.to_le_bytes())?;
+        self.writer.write_all(&byte_rate.to_le_bytes())?;
+        self.writer.write_all(&BLOCK_ALIGN.to_le_bytes())?;
+        self.writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        self.writer.write_all(b"data")?;
+        self.writer.write_all(&self.data_len.to_le_bytes())
+    }
+}
+
+fn to_pcm(value: f32) -> i16 {
+    (value.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
+
+/// Records APU output to a WAV file at a standard sample rate.
+pub struct WavRecorder {
+    resampler: Resampler,
+    writer: WavWriter<BufWriter<File>>,
+    buffer: Vec<StereoSample>,
+}
+
+impl WavRecorder {
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Self {
+            resampler: Resampler::new(apu::SAMPLE_RATE, sample_rate),
+            writer: WavWriter::new(file, sample_rate)?,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Resamples and writes the given APU samples, then updates the header so
+    /// the file is valid up to this point. The header is updated even if
+    /// writing fails, so the file stays valid once the size limit is reached.
+    pub fn record(&mut self, samples: impl IntoIterator<Item = StereoSample>) -> io::Result<()> {
+        self.resampler.process(samples, &mut self.buffer);
+        for sample in self.buffer.drain(..) {
+            if let Err(error) = self.writer.write_sample(sample) {
+                self.writer.flush()?;
+                return Err(error);
+            }
+        }
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn writes_header_and_samples() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 48_000).unwrap();
+        wav.write_sample(StereoSample { left: 1.0, right: -1.0 }).unwrap();
+        wav.write_sample(StereoSample { left: 0.0, right: 2.0 }).unwrap();
+        wav.flush().unwrap();
+
+        let bytes = wav.writer.into_inner();
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[4..8], &(36u32 + 8).to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(&bytes[22..24], &2u16.to_le_bytes());
+        assert_eq!(&bytes[24..28], &48_000u32.to_le_bytes());
+        assert_eq!(&bytes[28..32], &192_000u32.to_le_bytes());
+        assert_eq!(&bytes[34..36], &16u16.to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(&bytes[40..44], &8u32.to_le_bytes());
+        assert_eq!(&bytes[44..46], &i16::MAX.to_le_bytes());
+        assert_eq!(&bytes[46..48], &(-i16::MAX).to_le_bytes());
+        assert_eq!(&bytes[48..50], &0i16.to_le_bytes());
+        assert_eq!(&bytes[50..52], &i16::MAX.to_le_bytes());
+    }
+
+    #[test]
+    fn stops_at_size_limit() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 48_000).unwrap();
+        wav.data_len = MAX_DATA_LEN - 4;
+        let sample = StereoSample { left: 0.0, right: 0.0 };
+        wav.write_sample(sample).unwrap();
+        assert!(wav.write_sample(sample).is_err());
+        assert_eq!(wav.data_len, MAX_DATA_LEN);
+
+        wav.flush().unwrap();
+        let bytes = wav.writer.into_inner();
+        assert_eq!(bytes.len(), 44 + 4);
+        assert_eq!(&bytes[4..8], &u32::MAX.to_le_bytes());
+    }
+}
@@ -0,0 +1,214 @@
+use std::str::FromStr;
+
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+
+use crate::error::EmulationError;
+use crate::gb::Gameboy;
+use crate::joypad::Button;
+use crate::pacing::{FramePacer, Speed};
+use crate::playback::AudioPlayer;
+use crate::ppu::{Framebuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::screenshot::Palette;
+use crate::wav::WavRecorder;
+
+/// The keyboard key for each joypad button, indexed by [`Button`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct KeyMap([Key; Button::ALL.len()]);
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self([
+            Key::Right,
+            Key::Left,
+            Key::Up,
+            Key::Down,
+            Key::X,
+            Key::Z,
+            Key::Backspace,
+            Key::Enter,
+        ])
+    }
+}
+
+impl FromStr for KeyMap {
+    type Err = String;
+
+    /// Parses comma separated `button=key` pairs, e.g. `a=k,b=j`. Buttons that
+    /// aren't listed keep their default key.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut key_map = Self::default();
+        for binding in s.split(',') {
+            let (button, key) = binding
+                .split_once('=')
+                .ok_or_else(|| format!("expected <button>=<key>, got {binding}"))?;
+            let button: Button = button.trim().parse()?;
+            key_map.0[button as usize] = parse_key(key.trim())?;
+        }
+        Ok(key_map)
+    }
+}
+
+fn parse_key(name: &str) -> Result<Key, String> {
+    #[rustfmt::skip]
+    const LETTERS: [Key; 26] = [
+        Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K,
+        Key::L, Key::M, Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V,
+        Key::W, Key::X, Key::Y, Key::Z,
+    ];
+    #[rustfmt::skip]
+    const DIGITS: [Key; 10] = [
+        Key::Key0, Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5, Key::Key6, Key::Key7,
+        Key::Key8, Key::Key9,
+    ];
+
+    let lowercase = name.to_ascii_lowercase();
+    let key = match lowercase.as_bytes() {
+        [letter @ b'a'..=b'z'] => LETTERS[(letter - b'a') as usize],
+        [digit @ b'0'..=b'9'] => DIGITS[(digit - b'0') as usize],
+        _ => match lowercase.as_str() {
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "enter" => Key::Enter,
+            "space" => Key::Space,
+            "backspace" => Key::Backspace,
+            "tab" => Key::Tab,
+            "lshift" => Key::LeftShift,
+            "rshift" => Key::RightShift,
+            "lctrl" => Key::LeftCtrl,
+            "rctrl" => Key::RightCtrl,
+            "lalt" => Key::LeftAlt,
+            "ralt" => Key::RightAlt,
+            _ => return Err(format!("unknown key: {name}")),
+        },
+    };
+    Ok(key)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WindowError {
+    #[error(transparent)]
+    Window(#[from] minifb::Error),
+    #[error(transparent)]
+    Emulation(#[from] EmulationError),
+}
+
+/// Runs the emulator in a native window until it's closed, Escape is pressed
+/// or emulation fails, playing audio through the default output device if there
+/// is one.
+///
+/// `-` and `=` change the speed, which is shown in the title.
+pub fn run(
+    mut gameboy: Gameboy,
+    palette: Palette,
+    scale: usize,
+    key_map: KeyMap,
+    speed: Speed,
+    mut audio_out: Option<WavRecorder>,
+) -> Result<(), WindowError> {
+    let (width, height) = (SCREEN_WIDTH * scale, SCREEN_HEIGHT * scale);
+    let mut window = Window::new(&title(speed), width, height, WindowOptions::default())?;
+    // Frames are paced by the emulated clock instead.
+    window.set_target_fps(0);
+    let mut pacer = FramePacer::new(speed);
+    let mut pixels = vec![0; width * height];
+
+    let mut player = match AudioPlayer::open() {
+        Ok(player) => Some(player),
+        Err(error) => {
+            log::warn!("Audio playback disabled: {error}");
+            None
+        },
+    };
+
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        for (button, key) in Button::ALL.iter().zip(key_map.0) {
+            gameboy.set_button(*button, window.is_key_down(key));
+        }
+        let speed = if window.is_key_pressed(Key::Minus, KeyRepeat::No) {
+            pacer.speed().slower()
+        } else if window.is_key_pressed(Key::Equal, KeyRepeat::No) {
+            pacer.speed().faster()
+        } else {
+            pacer.speed()
+        };
+        if speed != pacer.speed() {
+            pacer.set_speed(speed);
+            window.set_title(&title(speed));
+        }
+
+        gameboy.run_frame()?;
+
+        let samples: Vec<_> = gameboy.audio_samples().collect();
+        if let Some(player) = &mut player {
+            player.play(samples.iter().copied());
+        }
+        if let Some(recorder) = &mut audio_out {
+            if let Err(error) = recorder.record(samples) {
+                log::error!("Failed to write audio output, recording stopped: {error}");
+                audio_out = None;
+            }
+        }
+
+        // Key presses are only picked up when the window is updated, which is
+        // skipped for frames that aren't displayed.
+        if pacer.next_frame() {
+            draw(gameboy.framebuffer(), palette, scale, &mut pixels);
+            window.update_with_buffer(&pixels, width, height)?;
+        }
+    }
+    Ok(())
+}
+
+fn title(speed: Speed) -> String {
+    if speed == Speed::NORMAL {
+        "boyo".to_owned()
+    } else {
+        format!("boyo ({speed})")
+    }
+}
+
+// Scales the framebuffer up by an integer factor into 0RGB pixels, which is
+// the format minifb expects.
+fn draw(framebuffer: &Framebuffer, palette: Palette, scale: usize, pixels: &mut [u32]) {
+    let width = SCREEN_WIDTH * scale;
+    for (y, row) in pixels.chunks_exact_mut(width).enumerate() {
+        let source = &framebuffer[y / scale * SCREEN_WIDTH..][..SCREEN_WIDTH];
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let [r, g, b] = palette.color(source[x / scale]);
+            *pixel = u32::from_be_bytes([0, r, g, b]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_key_map_overrides_defaults() {
+        let key_map: KeyMap = "a=k, B=j,start=space".parse().unwrap();
+        assert_eq!(key_map.0[Button::A as usize], Key::K);
+        assert_eq!(key_map.0[Button::B as usize], Key::J);
+        assert_eq!(key_map.0[Button::Start as usize], Key::Space);
+        assert_eq!(key_map.0[Button::Up as usize], Key::Up);
+
+        assert!("a".parse::<KeyMap>().is_err());
+        assert!("turbo=t".parse::<KeyMap>().is_err());
+        assert!("a=f13".parse::<KeyMap>().is_err());
+    }
+
+    #[test]
+    fn draw_scales_each_pixel_to_a_square() {
+        let mut framebuffer = Box::new([0; SCREEN_WIDTH * SCREEN_HEIGHT]);
+        framebuffer[0] = 3;
+        let mut pixels = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+        draw(&framebuffer, Palette::GRAYSCALE, 2, &mut pixels);
+
+        let width = SCREEN_WIDTH * 2;
+        assert_eq!(&pixels[..3], &[0x000000, 0x000000, 0xFFFFFF]);
+        assert_eq!(&pixels[width..width + 3], &[0x000000, 0x000000, 0xFFFFFF]);
+        assert_eq!(pixels[2 * width], 0xFFFFFF);
+    }
+}
@@ -0,0 +1,90 @@
+//! Runs Blargg's test ROMs, which print their results to the serial port.
+//!
+//! The ROMs aren't distributed with boyo. To run these tests, set
+//! `BOYO_BLARGG_DIR` to a directory laid out like the `gb-test-roms`
+//! repository, e.g. with `cpu_instrs/cpu_instrs.gb` in it. Tests whose ROM
+//! can't be found are skipped.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use boyo::{Capture, Gameboy, CLOCK_RATE, CYCLES_PER_FRAME};
+
+const ROM_DIR_VAR: &str = "BOYO_BLARGG_DIR";
+
+#[test]
+fn cpu_instrs() {
+    run_test_rom("cpu_instrs/cpu_instrs.gb", 60);
+}
+
+#[test]
+fn instr_timing() {
+    run_test_rom("instr_timing/instr_timing.gb", 10);
+}
+
+#[test]
+fn mem_timing() {
+    run_test_rom("mem_timing/mem_timing.gb", 10);
+}
+
+/// Runs the ROM at `path` (relative to the ROM directory) until it prints
+/// "Passed" or "Failed", failing the test unless it passed within
+/// `timeout_seconds` of emulated time.
+fn run_test_rom(path: &str, timeout_seconds: u64) {
+    let Some(dir) = std::env::var_os(ROM_DIR_VAR) else {
+        eprintln!("skipping {path}: {ROM_DIR_VAR} is not set");
+        return;
+    };
+    let path = PathBuf::from(dir).join(path);
+    if !path.is_file() {
+        eprintln!("skipping {}: not found", path.display());
+        return;
+    }
+
+    let mut gameboy = Gameboy::open(&path).unwrap_or_else(|error| panic!("{}", error));
+    let output = SerialOutput::default();
+    gameboy.set_serial_link(Box::new(Capture::new(output.clone())));
+
+    let timeout_cycles = timeout_seconds * CLOCK_RATE as u64 / 4;
+    for _ in 0..timeout_cycles / CYCLES_PER_FRAME as u64 {
+        if let Err(error) = gameboy.run_frame() {
+            panic!("{error}\nserial output:\n{}", output.text());
+        }
+        if let Some(lockup) = gameboy.lockup() {
+            panic!("{lockup}\nserial output:\n{}", output.text());
+        }
+
+        let text = output.text();
+        if text.contains("Passed") {
+            return;
+        }
+        if text.contains("Failed") {
+            panic!("serial output:\n{}", text);
+        }
+    }
+    panic!("timed out after {timeout_seconds} s\nserial output:\n{}", output.text());
+}
+
+// The serial link is owned by the Gameboy, so the test keeps a second handle
+// to the captured bytes.
+#[derive(Clone, Default)]
+struct SerialOutput(Rc<RefCell<Vec<u8>>>);
+
+impl SerialOutput {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for SerialOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+//! Runs every ROM in a local copy of the Mooneye test suite and prints a
+//! summary table of the results.
+//!
+//! The ROMs aren't distributed with boyo. To run this test, set
+//! `BOYO_MOONEYE_DIR` to a directory of Mooneye test ROMs, e.g. the
+//! `acceptance` directory of a mooneye-test-suite build. Every `.gb` file in
+//! it is run, including in subdirectories.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use boyo::{Gameboy, RegisterValue, CLOCK_RATE};
+
+const ROM_DIR_VAR: &str = "BOYO_MOONEYE_DIR";
+
+// Emulated time to wait for a ROM to finish. The tests only take a few seconds
+// on hardware.
+const TIMEOUT_SECONDS: u64 = 20;
+
+// What a ROM loads into B, C, D, E, H and L before executing LD B,B once it
+// has passed. A failed test loads 0x42 into all of them instead.
+const PASS_SIGNATURE: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+#[test]
+fn mooneye() {
+    let Some(dir) = std::env::var_os(ROM_DIR_VAR) else {
+        eprintln!("skipping: {ROM_DIR_VAR} is not set");
+        return;
+    };
+    let dir = PathBuf::from(dir);
+    let mut roms = Vec::new();
+    find_roms(&dir, &mut roms);
+    roms.sort();
+
+    let results: Vec<_> = roms.iter().map(|path| (path, run_test_rom(path))).collect();
+
+    let name_width =
+        results.iter().map(|(path, _)| display_name(&dir, path).len()).max().unwrap_or(0);
+    for (path, outcome) in &results {
+        println!("{:name_width$}  {}", display_name(&dir, path), outcome);
+    }
+
+    let passed = results.iter().filter(|(_, outcome)| matches!(outcome, Outcome::Passed)).count();
+    println!("{passed} passed, {} failed", results.len() - passed);
+    assert_eq!(passed, results.len(), "not every Mooneye test passed");
+}
+
+enum Outcome {
+    Passed,
+    /// The ROM finished, but its registers didn't match the pass signature.
+    Failed([u8; 6]),
+    /// Emulation failed or the CPU locked up before the ROM finished.
+    Error(String),
+    TimedOut,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Passed => write!(f, "PASS"),
+            Self::Failed(registers) => write!(f, "FAIL (registers: {registers:02X?})"),
+            Self::Error(error) => write!(f, "FAIL ({error})"),
+            Self::TimedOut => write!(f, "FAIL (timed out after {TIMEOUT_SECONDS} s)"),
+        }
+    }
+}
+
+fn run_test_rom(path: &Path) -> Outcome {
+    let mut gameboy = match Gameboy::open(path) {
+        Ok(gameboy) => gameboy,
+        Err(error) => return Outcome::Error(error.to_string()),
+    };
+
+    // The registers are checked right after the cycle that executed LD B,B,
+    // before the ROM has a chance to change them.
+    let timeout_cycles = TIMEOUT_SECONDS * CLOCK_RATE as u64 / 4;
+    for _ in 0..timeout_cycles {
+        if let Err(error) = gameboy.cycle() {
+            return Outcome::Error(error.to_string());
+        }
+        if let Some(lockup) = gameboy.lockup() {
+            return Outcome::Error(lockup.to_string());
+        }
+        if gameboy.take_software_breakpoint().is_some() {
+            let registers = signature_registers(&gameboy);
+            return if registers == PASS_SIGNATURE {
+                Outcome::Passed
+            } else {
+                Outcome::Failed(registers)
+            };
+        }
+    }
+    Outcome::TimedOut
+}
+
+fn signature_registers(gameboy: &Gameboy) -> [u8; 6] {
+    let mut registers = [0; 6];
+    for register in gameboy.registers() {
+        let index = ["b", "c", "d", "e", "h", "l"].iter().position(|name| *name == register.name);
+        if let (Some(index), RegisterValue::U8(value)) = (index, register.value) {
+            registers[index] = value;
+        }
+    }
+    registers
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let entries = std::fs::read_dir(dir)
+        .unwrap_or_else(|error| panic!("failed to read {}: {}", dir.display(), error));
+    for entry in entries {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension().is_some_and(|extension| extension == "gb") {
+            roms.push(path);
+        }
+    }
+}
+
+fn display_name(dir: &Path, path: &Path) -> String {
+    path.strip_prefix(dir).unwrap_or(path).display().to_string()
+}
@@ -0,0 +1,188 @@
+//! Runs the SM83 SingleStepTests, which give the state of the CPU and memory
+//! before and after a single instruction, along with the bus activity on every
+//! M-cycle in between.
+//!
+//! The tests aren't distributed with boyo. To run them, set `BOYO_SM83_DIR` to
+//! the `v1` directory of the test repository, which has a JSON file of tests
+//! for every opcode. Tests for instructions that boyo doesn't implement yet
+//! are skipped.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use boyo::{BusAccess, CpuState, EmulationError, Gameboy, TestBus};
+use serde_json::Value;
+
+const TEST_DIR_VAR: &str = "BOYO_SM83_DIR";
+
+#[test]
+fn sm83() {
+    let Some(dir) = std::env::var_os(TEST_DIR_VAR) else {
+        eprintln!("skipping: {TEST_DIR_VAR} is not set");
+        return;
+    };
+    let mut files: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .unwrap_or_else(|error| panic!("failed to read {:?}: {}", dir, error))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .collect();
+    files.sort();
+
+    let mut failed_files = 0;
+    for path in &files {
+        let summary = run_test_file(path);
+        println!("{}  {}", path.file_name().unwrap().to_string_lossy(), summary);
+        if summary.failed > 0 {
+            failed_files += 1;
+        }
+    }
+    println!("{} files, {failed_files} with failures", files.len());
+    assert_eq!(failed_files, 0, "not every SM83 test passed");
+}
+
+#[derive(Default)]
+struct Summary {
+    passed: usize,
+    failed: usize,
+    first_failure: Option<String>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.passed == 0 && self.failed == 0 {
+            return write!(f, "SKIP (not implemented)");
+        }
+        if self.failed == 0 {
+            return write!(f, "PASS ({} tests)", self.passed);
+        }
+        write!(f, "FAIL ({} of {} tests)", self.failed, self.passed + self.failed)?;
+        if let Some(failure) = &self.first_failure {
+            write!(f, ", first: {failure}")?;
+        }
+        Ok(())
+    }
+}
+
+fn run_test_file(path: &Path) -> Summary {
+    let text = std::fs::read_to_string(path)
+        .unwrap_or_else(|error| panic!("failed to read {}: {}", path.display(), error));
+    let tests: Vec<Value> = serde_json::from_str(&text)
+        .unwrap_or_else(|error| panic!("failed to parse {}: {}", path.display(), error));
+
+    let mut summary = Summary::default();
+    for test in &tests {
+        match run_test(test) {
+            Ok(()) => summary.passed += 1,
+            Err(Failure::NotImplemented) => {},
+            Err(Failure::Mismatch(message)) => {
+                summary.failed += 1;
+                summary.first_failure.get_or_insert_with(|| {
+                    format!("{}: {}", test["name"].as_str().unwrap_or("?"), message)
+                });
+            },
+        }
+    }
+    summary
+}
+
+enum Failure {
+    NotImplemented,
+    Mismatch(String),
+}
+
+// The tests overlap the fetch of each opcode with the end of the previous
+// instruction like the hardware does, so the opcode has already been fetched
+// from PC - 1 when a test starts, and the last cycle of a test fetches the
+// next opcode. boyo fetches an opcode in the first cycle of its instruction
+// instead, so it starts at PC - 1, and everything it does is compared one
+// cycle later than the test expects.
+fn run_test(test: &Value) -> Result<(), Failure> {
+    let mut cpu = cpu_state(&test["initial"]);
+    cpu.pc = cpu.pc.wrapping_sub(1);
+    let mut bus = TestBus::new();
+    for (address, value) in ram(&test["initial"]) {
+        bus.memory_mut()[address as usize] = value;
+    }
+    let mut gameboy = Gameboy::from_parts(cpu, bus);
+
+    match gameboy.step_instruction() {
+        Ok(()) => {},
+        Err(EmulationError::UnimplementedOpcode { .. }) => return Err(Failure::NotImplemented),
+        Err(error) => return Err(Failure::Mismatch(error.to_string())),
+    }
+
+    // The IME flag isn't emulated yet, so it isn't compared.
+    let mut expected_cpu = cpu_state(&test["final"]);
+    expected_cpu.pc = expected_cpu.pc.wrapping_sub(1);
+    if *gameboy.cpu() != expected_cpu {
+        return Err(Failure::Mismatch(format!(
+            "expected {:X?}, got {:X?}",
+            expected_cpu,
+            gameboy.cpu()
+        )));
+    }
+
+    for (address, expected) in ram(&test["final"]) {
+        let value = gameboy.bus().memory()[address as usize];
+        if value != expected {
+            return Err(Failure::Mismatch(format!(
+                "expected {expected:#04X} @ {address:#06X}, got {value:#04X}"
+            )));
+        }
+    }
+
+    let expected_cycles: Vec<_> = test["cycles"].as_array().unwrap().iter().map(access).collect();
+    let cycles = gameboy.bus_mut().take_cycles();
+    if cycles.len() != expected_cycles.len() || cycles[1..] != expected_cycles[..cycles.len() - 1] {
+        return Err(Failure::Mismatch(format!(
+            "expected cycles {:X?}, got {:X?}",
+            expected_cycles,
+            &cycles[1..]
+        )));
+    }
+    Ok(())
+}
+
+fn cpu_state(state: &Value) -> CpuState {
+    let register = |name: &str| state[name].as_u64().unwrap() as u8;
+    CpuState {
+        pc: state["pc"].as_u64().unwrap() as u16,
+        sp: state["sp"].as_u64().unwrap() as u16,
+        a: register("a"),
+        b: register("b"),
+        c: register("c"),
+        d: register("d"),
+        e: register("e"),
+        f: register("f"),
+        h: register("h"),
+        l: register("l"),
+    }
+}
+
+// The memory given for a state as (address, value) pairs. IE is in the flat
+// RAM like any other address.
+fn ram(state: &Value) -> Vec<(u16, u8)> {
+    let mut ram: Vec<_> = state["ram"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| (entry[0].as_u64().unwrap() as u16, entry[1].as_u64().unwrap() as u8))
+        .collect();
+    if let Some(ie) = state.get("ie").and_then(Value::as_u64) {
+        ram.push((0xFFFF, ie as u8));
+    }
+    ram
+}
+
+// A cycle is either null, when the bus is idle, or [address, value, pins],
+// where pins is e.g. "r-m" for a read or "-wm" for a write.
+fn access(cycle: &Value) -> Option<BusAccess> {
+    let cycle = cycle.as_array()?;
+    let address = cycle[0].as_u64()? as u16;
+    let value = cycle[1].as_u64()? as u8;
+    match cycle[2].as_str()?.as_bytes() {
+        [b'r', ..] => Some(BusAccess::Read { address, value }),
+        [_, b'w', ..] => Some(BusAccess::Write { address, value }),
+        _ => None,
+    }
+}